@@ -3,6 +3,12 @@
 //! le routeur, et les middlewares.
 pub mod handlers_auth;
 mod models;
-mod middlewares;
+pub(crate) mod middlewares;
+pub(crate) mod csrf;
+pub(crate) mod security_headers;
+pub(crate) mod rate_limit;
+pub(crate) mod ceremony_store;
 pub mod router;
+pub mod session_store;
 pub mod handlers_unauth;
+mod handlers_dev;
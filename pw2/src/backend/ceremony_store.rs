@@ -0,0 +1,156 @@
+//! Stockage des cérémonies WebAuthn en cours, entre leur début et leur fin.
+//! Une cérémonie expire après le timeout WebAuthn, le nombre de cérémonies conservées est borné
+//! et chacune ne peut être terminée que depuis la session qui l'a commencée.
+
+use crate::consts;
+use log::warn;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+use tower_sessions::{session, Session};
+use uuid::Uuid;
+
+/// Cérémonie en attente
+struct Ceremony<T> {
+    state: T,
+    created_at: Instant,
+}
+
+/// Cérémonies en attente d'un même type, identifiées par leur `state_id`
+pub struct CeremonyStore<T> {
+    session_key: &'static str, // Clé de session contenant l'identifiant de la cérémonie de la session
+    ttl: Duration,
+    capacity: usize,
+    ceremonies: Mutex<HashMap<Uuid, Ceremony<T>>>,
+}
+
+impl<T> CeremonyStore<T> {
+    /// Crée un store dont les cérémonies expirent avec le timeout WebAuthn
+    pub fn new(session_key: &'static str) -> Self {
+        Self::with_limits(
+            session_key,
+            Duration::from_secs(consts::WEBAUTHN_TIMEOUT_SECS),
+            consts::CEREMONY_STORE_CAPACITY,
+        )
+    }
+
+    fn with_limits(session_key: &'static str, ttl: Duration, capacity: usize) -> Self {
+        Self {
+            session_key,
+            ttl,
+            capacity,
+            ceremonies: Mutex::default(),
+        }
+    }
+
+    /// Enregistre une cérémonie et la lie à la session, retourne son identifiant.
+    /// Une cérémonie précédente de la même session ne peut plus être terminée.
+    pub fn insert(&self, session: &Session, state: T) -> Result<Uuid, session::Error> {
+        let id = Uuid::new_v4();
+        session.insert(self.session_key, id)?;
+        self.insert_at(id, state, Instant::now());
+        Ok(id)
+    }
+
+    fn insert_at(&self, id: Uuid, state: T, now: Instant) {
+        let mut ceremonies = self.lock();
+        if ceremonies.len() >= self.capacity {
+            ceremonies.retain(|_, ceremony| !self.is_expired(ceremony, now));
+        }
+        // Les cérémonies les plus anciennes sont abandonnées lorsque le store est plein
+        while ceremonies.len() >= self.capacity {
+            let Some(oldest) = ceremonies.iter().min_by_key(|(_, c)| c.created_at).map(|(id, _)| *id) else {
+                break;
+            };
+            ceremonies.remove(&oldest);
+            warn!("WebAuthn ceremony store is full, evicted the oldest ceremony");
+        }
+
+        ceremonies.insert(id, Ceremony { state, created_at: now });
+    }
+
+    /// Retire une cérémonie non expirée commencée par cette session et retourne son état
+    pub fn take(&self, session: &Session, id: &Uuid) -> Option<T> {
+        let bound = session.get::<Uuid>(self.session_key).ok().flatten();
+        if bound.as_ref() != Some(id) {
+            return None;
+        }
+        session.remove_value(self.session_key);
+        self.take_at(id, Instant::now())
+    }
+
+    fn take_at(&self, id: &Uuid, now: Instant) -> Option<T> {
+        let ceremony = self.lock().remove(id)?;
+        (!self.is_expired(&ceremony, now)).then_some(ceremony.state)
+    }
+
+    /// Supprime les cérémonies expirées, retourne leur nombre
+    pub fn sweep(&self) -> usize {
+        self.sweep_at(Instant::now())
+    }
+
+    fn sweep_at(&self, now: Instant) -> usize {
+        let mut ceremonies = self.lock();
+        let count = ceremonies.len();
+        ceremonies.retain(|_, ceremony| !self.is_expired(ceremony, now));
+        count - ceremonies.len()
+    }
+
+    fn is_expired(&self, ceremony: &Ceremony<T>, now: Instant) -> bool {
+        now.duration_since(ceremony.created_at) >= self.ttl
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Uuid, Ceremony<T>>> {
+        self.ceremonies.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(capacity: usize) -> CeremonyStore<u32> {
+        CeremonyStore::with_limits("test_ceremony", Duration::from_secs(60), capacity)
+    }
+
+    #[test]
+    fn test_ceremonies_expire_after_the_timeout() {
+        let store = store(10);
+        let start = Instant::now();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert_at(first, 1, start);
+        store.insert_at(second, 2, start + Duration::from_secs(30));
+
+        assert_eq!(store.sweep_at(start + Duration::from_secs(60)), 1);
+        assert_eq!(store.take_at(&first, start + Duration::from_secs(60)), None);
+        assert_eq!(store.take_at(&second, start + Duration::from_secs(90)), None);
+    }
+
+    #[test]
+    fn test_oldest_ceremonies_are_evicted_when_full() {
+        let store = store(2);
+        let start = Instant::now();
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for (i, id) in ids.iter().enumerate() {
+            store.insert_at(*id, i as u32, start + Duration::from_secs(i as u64));
+        }
+
+        assert_eq!(store.take_at(&ids[0], start), None);
+        assert_eq!(store.take_at(&ids[1], start), Some(1));
+        assert_eq!(store.take_at(&ids[2], start), Some(2));
+    }
+
+    #[test]
+    fn test_ceremonies_are_bound_to_their_session() {
+        let store = store(10);
+        let (owner, other) = (Session::new(None), Session::new(None));
+        let id = store.insert(&owner, 1).unwrap();
+
+        assert_eq!(store.take(&other, &id), None);
+        assert_eq!(store.take(&owner, &id), Some(1));
+        // A ceremony can only be completed once
+        assert_eq!(store.take(&owner, &id), None);
+    }
+}
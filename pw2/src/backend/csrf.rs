@@ -0,0 +1,115 @@
+//! Protection contre les requêtes intersites (CSRF).
+//! Chaque session reçoit un token, injecté dans les pages et renvoyé par le frontend avec chaque
+//! requête modifiant l'état. L'origine de ces requêtes est également vérifiée.
+
+use crate::config;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::warn;
+use tower_sessions::Session;
+use url::Url;
+use uuid::Uuid;
+
+/// Clé de session contenant le token CSRF
+const SESSION_CSRF_KEY: &str = "csrf_token";
+
+/// En-tête dans lequel le frontend envoie le token
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Champ contenant le token dans les formulaires HTML
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+
+/// Taille maximale d'un formulaire dont le token est extrait
+const MAX_FORM_BYTES: usize = 16 * 1024;
+
+/// Retourne le token CSRF de la session, en le créant si nécessaire
+pub fn token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(SESSION_CSRF_KEY) {
+        return token;
+    }
+
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    if session.insert(SESSION_CSRF_KEY, &token).is_err() {
+        warn!("Failed to store the CSRF token in the session");
+    }
+    token
+}
+
+/// Middleware refusant les requêtes modifiant l'état qui proviennent d'une autre origine
+/// ou qui ne contiennent pas le token CSRF de la session
+pub async fn protect(session: Session, request: Request, next: Next) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+
+    if !is_same_origin(request.headers()) {
+        return (StatusCode::FORBIDDEN, "Cross-site request refused").into_response();
+    }
+
+    let expected = session.get::<String>(SESSION_CSRF_KEY).ok().flatten();
+    let (request, submitted) = submitted_token(request).await;
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if constant_time_eq(expected.as_bytes(), submitted.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => (StatusCode::FORBIDDEN, "Invalid CSRF token").into_response(),
+    }
+}
+
+/// Vérifie que l'en-tête `Origin`, ou à défaut `Referer`, correspond à une origine de l'application.
+/// Les requêtes sans aucun de ces en-têtes ne sont protégées que par le token.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let source = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .map(HeaderValue::to_str);
+    let source = match source {
+        None => return true,
+        Some(Ok(source)) => source,
+        Some(Err(_)) => return false,
+    };
+
+    // Une origine opaque (`null`) n'est jamais acceptée
+    let Ok(source) = Url::parse(source) else {
+        return false;
+    };
+    let origin = source.origin();
+    origin.is_tuple() && config::get().origins().iter().any(|allowed| allowed.origin() == origin)
+}
+
+/// Extrait le token de l'en-tête dédié ou, pour un formulaire HTML, du champ dédié.
+/// Le corps du formulaire est lu puis remis en place pour le handler.
+async fn submitted_token(request: Request) -> (Request, Option<String>) {
+    if let Some(token) = request.headers().get(CSRF_HEADER).and_then(|value| value.to_str().ok()) {
+        let token = token.to_string();
+        return (request, Some(token));
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return (request, None);
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_FORM_BYTES).await else {
+        return (Request::from_parts(parts, Body::empty()), None);
+    };
+    let token = url::form_urlencoded::parse(&bytes)
+        .find(|(name, _)| name == CSRF_FORM_FIELD)
+        .map(|(_, value)| value.into_owned());
+    (Request::from_parts(parts, Body::from(bytes)), token)
+}
+
+/// Comparaison en temps constant, pour ne pas révéler le token par la durée de la vérification
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,64 +1,145 @@
 //! Gestion des routes nécessitant une authentification utilisateur.
 
-use crate::consts;
-use crate::utils::input::{validate_image, TextualContent};
-use anyhow::anyhow;
+use crate::backend::csrf;
+use crate::backend::security_headers::CspNonce;
+use crate::backend::handlers_unauth::{PendingRegistration, REGISTRATION_STATES};
+use crate::backend::middlewares::SessionUser;
+use crate::backend::models::{FeedPage, FeedParams, PasskeySummary, PostView, ReactionSummary};
+use crate::{config, consts};
+use crate::database::post::{self, FeedCursor, FeedSort, ImageVariant, Post, Reaction};
+use crate::database::upload;
+use crate::database::user::{self, User, DEFAULT_PASSKEY_NAME};
+use crate::utils::input::{validate_image, EncodedImage, ImageRejection, SanitizedImage, TextualContent};
+use crate::utils::webauthn::{begin_registration, complete_registration};
 use axum::{
-    extract::{Multipart, Query},
-    response::{Html, IntoResponse},
+    body::Body,
+    extract::{Multipart, Path, Query},
+    response::{Html, Response},
     Extension, Json,
 };
 use handlebars::Handlebars;
-use http::StatusCode;
-use once_cell::sync::Lazy;
-use serde::{Deserialize, Serialize};
-use serde_json::json;
+use http::{header, HeaderMap, StatusCode};
+use serde_json::{json, Value};
+use log::warn;
 use std::{
     collections::HashMap,
-    fs::{create_dir_all, File},
-    io::Write,
-    path::Path,
-    sync::{Arc, RwLock},
+    ops::RangeInclusive,
+    sync::Arc,
 };
+use tower_sessions::Session;
 use uuid::Uuid;
-
-/// Modèle représentant un post avec des likes
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct Post {
-    pub id: Uuid,
-    pub content: String,
-    pub image_path: Option<String>,
-    pub likes: i32,
+use webauthn_rs::prelude::{CredentialID, RegisterPublicKeyCredential};
+
+/// Charge une page du fil et la prépare pour l'affichage
+fn load_feed(params: &FeedParams, user: &User) -> Result<(FeedPage, Option<FeedCursor>), (StatusCode, &'static str)> {
+    let feed = post::feed(&params.to_query()?)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load posts"))?;
+
+    // Les auteurs sont chargés une seule fois chacun
+    let mut authors: HashMap<String, Option<User>> = HashMap::new();
+    let posts = feed
+        .posts
+        .into_iter()
+        .map(|post| {
+            PostView::new(post, user, |email| {
+                authors
+                    .entry(email.to_string())
+                    .or_insert_with(|| user::get(email))
+                    .clone()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let page = FeedPage {
+        posts,
+        next_cursor: feed.next_cursor.map(|cursor| cursor.to_string()),
+    };
+    Ok((page, feed.next_cursor))
 }
 
-/// Base de données statique pour les posts (simulée en mémoire)
-static POSTS: Lazy<RwLock<Vec<Post>>> = Lazy::new(|| {
-    RwLock::new(vec![])
-});
-
-/// Affiche la page principale avec la liste des posts
+/// Affiche la page principale avec une page du fil
 pub async fn home(
     Extension(hbs): Extension<Arc<Handlebars<'_>>>,
-    Query(params): Query<HashMap<String, String>>,
-) -> impl IntoResponse {
-    let user = params.get("user").cloned().unwrap_or_else(|| "Guest".to_string());
+    Extension(CspNonce(nonce)): Extension<CspNonce>,
+    session: Session,
+    SessionUser(user): SessionUser,
+    Query(params): Query<FeedParams>,
+) -> axum::response::Result<Html<String>> {
+    let (page, next_cursor) = load_feed(&params, &user)?;
+
+    // Liens de tri, de filtre et de pagination, conservant les autres paramètres
+    let sort_link = |sort: FeedSort| {
+        FeedParams {
+            sort,
+            cursor: None,
+            ..params.clone()
+        }
+        .home_link()
+    };
+    let author_link = |author: Option<&str>| {
+        FeedParams {
+            author: author.map(str::to_string),
+            cursor: None,
+            ..params.clone()
+        }
+        .home_link()
+    };
+    let authors: HashMap<&str, String> = page
+        .posts
+        .iter()
+        .filter_map(|post| post.author.as_deref())
+        .map(|author| (author, author_link(Some(author))))
+        .collect();
+    let links = json!({
+        "newest": sort_link(FeedSort::Newest),
+        "likes": sort_link(FeedSort::Likes),
+        "comments": sort_link(FeedSort::Comments),
+        "authors": authors,
+        "all_authors": author_link(None),
+        "next": next_cursor.map(|cursor| params.next(cursor).home_link()),
+    });
+
+    let author_filter = params
+        .author
+        .as_deref()
+        .and_then(|author| Uuid::parse_str(author).ok())
+        .and_then(|handle| user::find_by_user_handle(&handle))
+        .map(|author| format!("{} {}", author.first_name, author.last_name));
+
     let data = json!({
-        "user": user,
-        "posts": *POSTS.read().unwrap(),
+        "user": format!("{} {}", user.first_name, user.last_name),
+        "posts": page.posts,
+        "sort": params.sort,
+        "author_filter": author_filter,
+        "links": links,
+        "csrf_token": csrf::token(&session),
+        "csp_nonce": nonce,
     });
 
-    match hbs.render("home", &data) {
-        Ok(body) => Html(body),
-        Err(_) => Html("<h1>Internal Server Error</h1>".to_string()),
-    }
+    hbs.render("home", &data)
+        .map(Html)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into())
+}
+
+/// Retourne une page du fil en JSON
+pub async fn feed(
+    SessionUser(user): SessionUser,
+    Query(params): Query<FeedParams>,
+) -> axum::response::Result<Json<FeedPage>> {
+    let (page, _) = load_feed(&params, &user)?;
+    Ok(Json(page))
 }
 
 /// Crée un nouveau post avec texte et image
-pub async fn create_post(mut multipart: Multipart) -> axum::response::Result<Json<serde_json::Value>> {
+pub async fn create_post(
+    SessionUser(user): SessionUser,
+    mut multipart: Multipart,
+) -> axum::response::Result<Json<serde_json::Value>> {
     let mut text_content = None;
-    let mut uploaded_file_path = None;
+    let mut encoded_image = None;
+    let limits = config::get().uploads.image_limits();
 
-    while let Some(field) = multipart.next_field().await? {
+    while let Some(mut field) = multipart.next_field().await? {
         let field_name = field.name().unwrap_or_default().to_string();
 
         if field_name == "text" {
@@ -66,125 +147,453 @@ pub async fn create_post(mut multipart: Multipart) -> axum::response::Result<Jso
             text_content = TextualContent::try_new_long_form_content(&text);
         } else if field_name == "file" {
             let original_filename = field.file_name().unwrap_or_default().to_string();
-            let filename = format!("{}.jpg", Uuid::new_v4());
-            let file_bytes = field.bytes().await?;
-
-            if !validate_image(&file_bytes, &original_filename).is_ok() {
-                return Err((StatusCode::BAD_REQUEST, "Invalid image file").into());
-            }
 
-            let uploads_dir = consts::UPLOADS_DIR;
-            if !Path::new(uploads_dir).exists() {
-                create_dir_all(uploads_dir).unwrap();
+            // Le fichier est lu par morceaux, et abandonné dès qu'il dépasse la limite
+            let mut file_bytes = Vec::new();
+            while let Some(chunk) = field.chunk().await? {
+                if file_bytes.len() + chunk.len() > limits.max_file_bytes {
+                    return Err(image_rejection(ImageRejection::FileTooLarge {
+                        limit: limits.max_file_bytes,
+                    }));
+                }
+                file_bytes.extend_from_slice(&chunk);
             }
 
-            let file_path = format!("{}/{}", uploads_dir, filename);
-            let mut file = File::create(&file_path).unwrap();
-
-            file.write_all(&file_bytes).unwrap();
-
-            // Chemin relatif utilisé par le frontend
-            uploaded_file_path = Some(format!("/uploads/{}", filename));
+            // Seules les versions ré-encodées de l'image sont enregistrées.
+            // Le décodage et le redimensionnement sont coûteux et ne doivent pas bloquer les autres requêtes.
+            let encoded = tokio::task::spawn_blocking(move || {
+                let image = validate_image(&file_bytes, &original_filename, &limits)?;
+                let variants = encode_image_variants(&image)?;
+                Ok((variants, image.extension()))
+            })
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to process image"))?;
+            encoded_image = Some(encoded.map_err(image_rejection)?);
         }
     }
 
     let text = text_content.ok_or((StatusCode::BAD_REQUEST, "Text content is required"))?;
-    let image_path = uploaded_file_path;
 
-    let post_id = save_post(&text.as_ref(), image_path.as_deref());
+    // Les fichiers ne doivent pas être supprimés entre leur écriture et l'enregistrement du post
+    let _guard = upload::lock();
+    let image_variants = match &encoded_image {
+        Some((variants, extension)) => store_image_variants(variants, extension)?,
+        None => Vec::new(),
+    };
+    let paths: Vec<String> = image_variants.iter().map(|variant| variant.path.clone()).collect();
+    let post_id = match post::create(&user.email, text.as_ref(), image_variants) {
+        Ok(post_id) => post_id,
+        Err(_) => {
+            if let Err(e) = upload::release(paths.iter().map(String::as_str)) {
+                warn!("Failed to release the images of a rejected post: {}", e);
+            }
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save post").into());
+        }
+    };
 
     Ok(Json(json!({ "post_id": post_id })))
 }
 
-/// Sauvegarde des posts dans un fichier YAML
-pub fn save_posts_to_file() -> Result<(), anyhow::Error> {
-    let posts = POSTS.read().map_err(|_| anyhow!("Failed to read posts"))?; // Lecture des posts existants
-    let file_path = consts::POSTS_DB_PATH;
-    let file_dir = Path::new(file_path).parent().unwrap();
+/// Réponse d'erreur correspondant au refus d'une image
+fn image_rejection(rejection: ImageRejection) -> axum::response::ErrorResponse {
+    let status = match rejection {
+        ImageRejection::FileTooLarge { .. }
+        | ImageRejection::DimensionsTooLarge { .. }
+        | ImageRejection::TooManyPixels { .. }
+        | ImageRejection::DecoderLimitExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+        ImageRejection::EncodingFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, rejection.to_string()).into()
+}
 
-    if !file_dir.exists() {
-        create_dir_all(file_dir).or(Err(anyhow!("Failed to create directory for posts.")))?;
-    }
+/// Génère les différentes tailles d'une image uploadée, de la plus petite à la plus grande
+fn encode_image_variants(image: &SanitizedImage) -> Result<Vec<EncodedImage>, ImageRejection> {
+    let mut variants: Vec<EncodedImage> = Vec::new();
+    for (_, max_size) in consts::IMAGE_VARIANTS {
+        let encoded = image.encode(max_size)?;
 
-    let file = File::create(file_path).or(Err(anyhow!("Failed to create posts.yaml.")))?;
-    serde_yaml::to_writer(file, &*posts).or(Err(anyhow!("Failed to serialize posts to YAML.")))?;
-    Ok(())
+        // Une image plus petite que la taille demandée n'est pas dupliquée
+        if variants.last().is_some_and(|last| (last.width, last.height) == (encoded.width, encoded.height)) {
+            continue;
+        }
+        variants.push(encoded);
+    }
+    Ok(variants)
 }
 
-/// Charge les posts depuis un fichier YAML
-pub fn load_posts_from_file() -> Result<(), anyhow::Error> {
-    let file_path = consts::POSTS_DB_PATH;
+/// Enregistre les tailles d'une image, doit être appelé en tenant `upload::lock`
+fn store_image_variants(
+    variants: &[EncodedImage],
+    extension: &str,
+) -> Result<Vec<ImageVariant>, (StatusCode, &'static str)> {
+    variants
+        .iter()
+        .map(|variant| {
+            let path = upload::store(&variant.bytes, extension)
+                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save image"))?;
+            Ok(ImageVariant {
+                path,
+                width: variant.width,
+                height: variant.height,
+            })
+        })
+        .collect()
+}
 
-    if Path::new(file_path).exists() {
-        let file = File::open(file_path).or(Err(anyhow!("Failed to open posts.yaml.")))?;
-        let loaded_posts: Vec<Post> = serde_yaml::from_reader(file).unwrap_or_default();
+/// Type MIME d'une image uploadée, selon son extension
+fn upload_content_type(name: &str) -> Option<&'static str> {
+    match name.rsplit_once('.')?.1 {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "webp" => Some("image/webp"),
+        "gif" => Some("image/gif"),
+        _ => None,
+    }
+}
 
-        let mut posts = POSTS.write().map_err(|_| anyhow!("Failed to write posts"))?;
-        *posts = loaded_posts;
+/// Intervalle demandé par un en-tête `Range`, borné à la taille du fichier.
+/// Seuls les intervalles uniques sont pris en charge, `None` signifie que tout le fichier est envoyé.
+fn requested_range(headers: &HeaderMap, len: u64, etag: &str) -> Option<Result<RangeInclusive<u64>, ()>> {
+    let range = headers.get(header::RANGE)?.to_str().ok()?.strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    // L'intervalle ne s'applique que si le fichier n'a pas changé
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        if if_range.as_bytes() != etag.as_bytes() {
+            return None;
+        }
     }
 
-    Ok(())
+    let (start, end) = range.trim().split_once('-')?;
+    let range = match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        (Some(start), Some(end)) if start <= end => start..=end.min(len.saturating_sub(1)),
+        (Some(start), None) if end.is_empty() => start..=len.saturating_sub(1),
+        (None, Some(suffix)) if start.is_empty() && suffix > 0 => len.saturating_sub(suffix)..=len.saturating_sub(1),
+        _ => return Some(Err(())),
+    };
+    if len == 0 || *range.start() >= len {
+        return Some(Err(()));
+    }
+    Some(Ok(range))
 }
 
-/// Simule la sauvegarde d'un post dans une base de données
-fn save_post(text: &str, image_path: Option<&str>) -> String {
-    let new_post = Post {
-        id: Uuid::new_v4(),
-        content: text.to_string(),
-        image_path: image_path.map(|path| path.to_string()),
-        likes: 0,
+/// Sert une image uploadée, uniquement si elle appartient à un post visible de l'utilisateur.
+/// Tous les posts sont visibles des utilisateurs connectés : un fichier qui n'est référencé par
+/// aucun post n'est pas servi.
+pub async fn serve_upload(
+    SessionUser(_user): SessionUser,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    const NOT_FOUND: (StatusCode, &str) = (StatusCode::NOT_FOUND, "Image not found");
+
+    let path = format!("{}{}", upload::PUBLIC_PREFIX, name);
+    let file_path = upload::file_path(&path).ok_or(NOT_FOUND)?;
+    let content_type = upload_content_type(&name).ok_or(NOT_FOUND)?;
+    let referenced = upload::is_referenced(&path)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load image"))?;
+    if !referenced {
+        return Err(NOT_FOUND.into());
+    }
+    let bytes = tokio::fs::read(&file_path).await.map_err(|_| NOT_FOUND)?;
+
+    // Le nom du fichier identifie son contenu, il ne change jamais
+    let etag = format!("\"{}\"", name.rsplit_once('.').map_or(name.as_str(), |(stem, _)| stem));
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::CONTENT_DISPOSITION, format!("inline; filename=\"{}\"", name))
+        .header(header::CACHE_CONTROL, "private, max-age=31536000, immutable")
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| matches!(tag.trim(), "*") || tag.trim() == etag));
+    let len = bytes.len() as u64;
+    let response = if not_modified {
+        response.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+    } else {
+        match requested_range(&headers, len, &etag) {
+            None => response.body(Body::from(bytes)),
+            Some(Ok(range)) => response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start(), range.end(), len))
+                .body(Body::from(bytes[*range.start() as usize..=*range.end() as usize].to_vec())),
+            Some(Err(())) => response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty()),
+        }
     };
 
-    let post_id = new_post.id.to_string();
+    response.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load image").into())
+}
+
+/// Extrait l'identifiant de post d'une requête JSON
+fn post_id_from_payload(payload: &Value) -> Result<Uuid, (StatusCode, &'static str)> {
+    let post_id = payload
+        .get("post_id")
+        .and_then(|v| v.as_str())
+        .ok_or((StatusCode::BAD_REQUEST, "Post ID is required"))?;
+    Uuid::parse_str(post_id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Post ID"))
+}
 
-    {
-        let mut posts = POSTS.write().unwrap();
-        posts.push(new_post);
+/// Retourne le post demandé, à condition qu'il appartienne à l'utilisateur
+fn owned_post(post_id: &Uuid, user: &User) -> Result<Post, (StatusCode, &'static str)> {
+    let post = post::get(post_id).ok_or((StatusCode::NOT_FOUND, "Post not found"))?;
+    if post.author.is_empty() || post.author != user.email {
+        return Err((StatusCode::FORBIDDEN, "You can only modify your own posts"));
     }
+    Ok(post)
+}
 
-    if let Err(e) = save_posts_to_file() {
-        eprintln!("Failed to save posts: {}", e);
+/// Modifie le texte d'un post de l'utilisateur
+pub async fn edit_post(
+    SessionUser(user): SessionUser,
+    Json(body): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let post_id = post_id_from_payload(&body)?;
+    let text = body
+        .get("text")
+        .and_then(|v| v.as_str())
+        .and_then(TextualContent::try_new_long_form_content)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid text content"))?;
+
+    owned_post(&post_id, &user)?;
+    post::edit(&post_id, text.as_ref())
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update post"))?;
+    Ok(StatusCode::OK)
+}
+
+/// Supprime un post de l'utilisateur et son image
+pub async fn delete_post(
+    SessionUser(user): SessionUser,
+    Json(body): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let post_id = post_id_from_payload(&body)?;
+    let post = owned_post(&post_id, &user)?;
+
+    // Les images encore utilisées par d'autres posts sont conservées
+    let _guard = upload::lock();
+    post::delete(&post_id).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete post"))?;
+    if let Err(e) = upload::release(post.image_paths()) {
+        warn!("Failed to release the images of post {}: {}", post_id, e);
     }
+    Ok(StatusCode::OK)
+}
 
-    post_id
+/// Extrait un identifiant optionnel d'une requête JSON
+fn optional_id_from_payload(payload: &Value, field: &str) -> Result<Option<Uuid>, (StatusCode, &'static str)> {
+    match payload.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .map(Some)
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid identifier")),
+    }
 }
 
-/// Permet de like un post
-pub async fn like_post(Json(body): Json<serde_json::Value>) -> axum::response::Result<StatusCode> {
-    let post_id = body
-        .get("post_id")
+/// Ajoute un commentaire à un post, ou une réponse à un commentaire si `parent_id` est donné
+pub async fn add_comment(
+    SessionUser(user): SessionUser,
+    Json(body): Json<Value>,
+) -> axum::response::Result<Json<Value>> {
+    let post_id = post_id_from_payload(&body)?;
+    let parent_id = optional_id_from_payload(&body, "parent_id")?;
+    let text = body
+        .get("text")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Post ID is required"))?;
-    let post_id = Uuid::parse_str(post_id).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Post ID"))?;
+        .and_then(TextualContent::try_new_long_form_content)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid text content"))?;
 
-    let action = body
-        .get("action")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Action is required"))?;
-
-    let mut posts = POSTS.write().map_err(|_| (StatusCode::BAD_REQUEST, "Failed to write posts"))?;
-    let post = posts.iter_mut().find(|post| post.id == post_id);
-
-    if let Some(post) = post {
-        match action {
-            "like" => {
-                if post.likes == 1 {
-                    post.likes = 0;
-                } else {
-                    post.likes = 1;
-                }
-            }
-            "dislike" => {
-                if post.likes == -1 {
-                    post.likes = 0;
-                } else {
-                    post.likes = -1;
-                }
-            }
-            _ => return Err((StatusCode::BAD_REQUEST, "Invalid action").into()),
-        }
-        return Ok(StatusCode::OK);
+    let post = post::get(&post_id).ok_or((StatusCode::NOT_FOUND, "Post not found"))?;
+    if parent_id.is_some_and(|parent_id| !post.can_reply_to(&parent_id)) {
+        return Err((StatusCode::BAD_REQUEST, "Replies are only allowed on top-level comments").into());
+    }
+
+    let comment = post::add_comment(&post_id, &user.email, text.as_ref(), parent_id)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save comment"))?;
+    Ok(Json(json!({ "comment_id": comment.id })))
+}
+
+/// Supprime un commentaire de l'utilisateur, ainsi que ses réponses
+pub async fn delete_comment(
+    SessionUser(user): SessionUser,
+    Json(body): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let post_id = post_id_from_payload(&body)?;
+    let comment_id = optional_id_from_payload(&body, "comment_id")?
+        .ok_or((StatusCode::BAD_REQUEST, "Comment ID is required"))?;
+
+    let post = post::get(&post_id).ok_or((StatusCode::NOT_FOUND, "Post not found"))?;
+    let comment = post.comment(&comment_id).ok_or((StatusCode::NOT_FOUND, "Comment not found"))?;
+    if comment.author != user.email {
+        return Err((StatusCode::FORBIDDEN, "You can only delete your own comments").into());
+    }
+
+    post::delete_comment(&post_id, &comment_id)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete comment"))?;
+    Ok(StatusCode::OK)
+}
+
+/// Permet de like ou dislike un post, une seconde réaction identique la retire.
+/// Retourne les compteurs mis à jour.
+pub async fn like_post(
+    SessionUser(user): SessionUser,
+    Json(body): Json<serde_json::Value>,
+) -> axum::response::Result<Json<ReactionSummary>> {
+    let post_id = post_id_from_payload(&body)?;
+
+    let reaction = match body.get("action").and_then(|v| v.as_str()) {
+        Some("like") => Reaction::Like,
+        Some("dislike") => Reaction::Dislike,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "Invalid action").into()),
+        None => return Err((StatusCode::BAD_REQUEST, "Action is required").into()),
+    };
+
+    if post::get(&post_id).is_none() {
+        return Err((StatusCode::NOT_FOUND, "Post not found").into());
+    }
+
+    let post = post::react(&post_id, &user.email, reaction)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update post"))?;
+    Ok(Json(ReactionSummary::new(&post, &user)))
+}
+
+/// Extrait l'identifiant de passkey d'une requête JSON
+fn credential_id_from_payload(payload: &Value) -> Result<CredentialID, (StatusCode, &'static str)> {
+    payload
+        .get("credential_id")
+        .and_then(|v| serde_json::from_value::<CredentialID>(v.clone()).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Credential ID is required"))
+}
+
+/// Affiche la liste des passkeys de l'utilisateur connecté
+pub async fn passkeys_page(
+    Extension(hbs): Extension<Arc<Handlebars<'_>>>,
+    Extension(CspNonce(nonce)): Extension<CspNonce>,
+    session: Session,
+    SessionUser(user): SessionUser,
+) -> axum::response::Result<Html<String>> {
+    let passkeys = user.passkeys.iter().map(PasskeySummary::from).collect::<Vec<_>>();
+
+    let data = json!({
+        "passkeys": passkeys,
+        "csrf_token": csrf::token(&session),
+        "csp_nonce": nonce,
+    });
+    hbs.render("passkeys", &data)
+        .map(Html)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.").into())
+}
+
+/// Début de l'ajout d'une passkey supplémentaire à l'utilisateur connecté
+pub async fn passkey_register_begin(
+    session: Session,
+    SessionUser(user): SessionUser,
+) -> axum::response::Result<Json<Value>> {
+    let email = user.email;
+
+    // Exclude every passkey already known for this user
+    let known_passkeys = user.passkeys.into_iter().map(|pk| pk.passkey).collect::<Vec<_>>();
+
+    // Every passkey of a user shares the same user handle
+    let user_handle = user::user_handle(&email)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
+
+    let (pk, state) = begin_registration(user_handle, &email, &email, &known_passkeys)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
+
+    let pending = PendingRegistration {
+        email: email.clone(),
+        user_handle,
+        state,
+    };
+    let state_id = REGISTRATION_STATES
+        .insert(&session, pending)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
+
+    Ok(Json(json!({
+        "publicKey": pk,
+        "state_id": state_id,
+    })))
+}
+
+/// Fin de l'ajout d'une passkey supplémentaire à l'utilisateur connecté
+pub async fn passkey_register_complete(
+    session: Session,
+    SessionUser(user): SessionUser,
+    Json(payload): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let email = user.email;
+
+    let name = payload
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_PASSKEY_NAME);
+    let name = TextualContent::try_new_short_form_content(name)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid passkey name"))?;
+
+    let state_id = payload
+        .get("state_id")
+        .and_then(Value::as_str)
+        .and_then(|v| Uuid::parse_str(v).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid request parameters"))?;
+    let pending = REGISTRATION_STATES
+        .take(&session, &state_id)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid registration session"))?;
+    if pending.email != email {
+        return Err((StatusCode::BAD_REQUEST, "Invalid registration session").into());
     }
 
-    Err((StatusCode::NOT_FOUND, "Post not found").into())
+    let cred = payload
+        .get("response")
+        .and_then(|v| serde_json::from_value::<RegisterPublicKeyCredential>(v.clone()).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid response"))?;
+
+    let passkey = complete_registration(&cred, &pending.state)
+        .map_err(|_| (StatusCode::FORBIDDEN, "Failed to complete registration"))?;
+
+    user::add_passkey(&email, name.as_ref(), passkey)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Failed to complete registration"))?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Renomme une passkey de l'utilisateur connecté
+pub async fn passkey_rename(
+    SessionUser(user): SessionUser,
+    Json(payload): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let email = user.email;
+    let cred_id = credential_id_from_payload(&payload)?;
+    let name = payload
+        .get("name")
+        .and_then(Value::as_str)
+        .and_then(TextualContent::try_new_short_form_content)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid passkey name"))?;
+
+    user::rename_passkey(&email, &cred_id, name.as_ref())
+        .map_err(|_| (StatusCode::NOT_FOUND, "Passkey not found"))?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Révoque une passkey de l'utilisateur connecté
+pub async fn passkey_revoke(
+    SessionUser(user): SessionUser,
+    Json(payload): Json<Value>,
+) -> axum::response::Result<StatusCode> {
+    let email = user.email;
+    let cred_id = credential_id_from_payload(&payload)?;
+
+    user::remove_passkey(&email, &cred_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Failed to revoke passkey"))?;
+
+    Ok(StatusCode::OK)
 }
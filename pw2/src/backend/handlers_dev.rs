@@ -0,0 +1,68 @@
+//! Routes de développement, disponibles uniquement dans les builds de debug.
+//! Contient une boîte de réception permettant de consulter les emails simulés.
+
+use crate::backend::csrf;
+use crate::backend::security_headers::CspNonce;
+use crate::database::email::{self, Email};
+use crate::HBS;
+use axum::{
+    extract::Path,
+    Extension,
+    http::StatusCode,
+    response::{Html, Redirect},
+};
+use serde_json::json;
+use tower_sessions::Session;
+
+/// Extrait les liens contenus dans le corps texte d'un email
+fn extract_links(body: &str) -> Vec<&str> {
+    body.split_whitespace()
+        .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+        .collect()
+}
+
+/// Affiche la boîte de réception, avec l'email sélectionné le cas échéant
+fn render_inbox(session: &Session, nonce: &str, selected: Option<Email>) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let emails = email::all().map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error."))?;
+    let emails = emails
+        .iter()
+        .map(|email| json!({ "email": email, "links": extract_links(&email.body) }))
+        .collect::<Vec<_>>();
+    let selected = selected.map(|email| json!({ "links": extract_links(&email.body), "email": email }));
+
+    HBS.render("dev_mail", &json!({
+        "emails": emails,
+        "selected": selected,
+        "csrf_token": csrf::token(session),
+        "csp_nonce": nonce,
+    }))
+        .map(Html)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error."))
+}
+
+/// Liste les emails envoyés
+pub async fn mail_inbox(
+    session: Session,
+    Extension(CspNonce(nonce)): Extension<CspNonce>,
+) -> axum::response::Result<Html<String>> {
+    Ok(render_inbox(&session, &nonce, None)?)
+}
+
+/// Affiche un email
+pub async fn mail_view(
+    session: Session,
+    Extension(CspNonce(nonce)): Extension<CspNonce>,
+    Path(pk): Path<u64>,
+) -> axum::response::Result<Html<String>> {
+    let email = email::get(pk).ok_or((StatusCode::NOT_FOUND, "Email not found"))?;
+    Ok(render_inbox(&session, &nonce, Some(email))?)
+}
+
+/// Supprime un email
+pub async fn mail_delete(Path(pk): Path<u64>) -> axum::response::Result<Redirect> {
+    match email::delete(pk) {
+        Ok(true) => Ok(Redirect::to("/dev/mail")),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Email not found").into()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.").into()),
+    }
+}
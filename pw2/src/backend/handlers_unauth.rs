@@ -2,72 +2,127 @@
 //! Contient les handlers pour les pages publiques, l'inscription, la connexion,
 //! la récupération de compte et la validation d'utilisateur.
 
-use crate::database::{token, user};
-use crate::email::send_mail;
+use crate::backend::ceremony_store::CeremonyStore;
+use crate::backend::{csrf, rate_limit};
+use crate::backend::security_headers::CspNonce;
+use crate::backend::middlewares::SESSION_USER_KEY;
+use crate::consts;
+use crate::database::{token, unix_timestamp, user};
+use crate::database::user::DEFAULT_PASSKEY_NAME;
+use crate::email::{send_recovery_mail, send_verification_mail};
 use crate::utils::input::{TextualContent, UserEmail};
-use crate::utils::webauthn::{begin_authentication, begin_registration, complete_authentication, complete_registration, CREDENTIAL_STORE};
+use crate::utils::webauthn::{
+    begin_authentication, begin_discoverable_authentication, begin_registration, complete_authentication,
+    complete_discoverable_authentication, complete_registration, identify_discoverable_authentication,
+};
 use crate::HBS;
 use axum::{
     extract::{Json, Path, Query},
+    Extension,
     http::StatusCode,
     response::{Html, IntoResponse, Redirect},
 };
-use log::{debug, error};
+use log::error;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use tokio::sync::RwLock;
 use tower_sessions::Session;
 use uuid::Uuid;
-use webauthn_rs::prelude::{PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential};
-
-/// Stockage des états d'enregistrement et d'authentification
-pub(crate) static REGISTRATION_STATES: Lazy<RwLock<HashMap<String, PasskeyRegistration>>> = Lazy::new(Default::default);
-static AUTHENTICATION_STATES: Lazy<RwLock<HashMap<String, PasskeyAuthentication>>> = Lazy::new(Default::default);
-
-/// Ensures that the webauthn is aware of the user's, if it is stored in the database.
-async fn ensure_store_contains_known_user_passkey(email: &str) {
-    let mut store = CREDENTIAL_STORE.write().await;
-    if store.get(email).is_none() {
-        if let Ok(Some(passkey)) = user::get_passkey(email) {
-            store.insert(email.to_string(), passkey);
-        } else {
-            debug!("No passkey found for user {}", email);
-        }
-    }
+use webauthn_rs::prelude::{
+    DiscoverableAuthentication, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+    RegisterPublicKeyCredential,
+};
+
+/// Enregistrement de passkey en cours
+pub(crate) struct PendingRegistration {
+    pub email: String,     // Email pour lequel l'enregistrement a été commencé
+    pub user_handle: Uuid, // Identifiant WebAuthn donné à la passkey
+    pub state: PasskeyRegistration,
+}
+
+/// Stockage des états d'enregistrement et d'authentification, avec l'email pour lequel ils ont été créés
+pub(crate) static REGISTRATION_STATES: Lazy<CeremonyStore<PendingRegistration>> =
+    Lazy::new(|| CeremonyStore::new("registration_state"));
+static AUTHENTICATION_STATES: Lazy<CeremonyStore<(String, PasskeyAuthentication)>> =
+    Lazy::new(|| CeremonyStore::new("authentication_state"));
+
+/// Stockage des états d'authentification sans email, l'utilisateur n'étant connu qu'à la fin de la cérémonie
+static DISCOVERABLE_STATES: Lazy<CeremonyStore<DiscoverableAuthentication>> =
+    Lazy::new(|| CeremonyStore::new("discoverable_authentication_state"));
+
+/// Supprime les cérémonies WebAuthn expirées, retourne leur nombre
+pub fn sweep_expired_ceremonies() -> usize {
+    REGISTRATION_STATES.sweep() + AUTHENTICATION_STATES.sweep() + DISCOVERABLE_STATES.sweep()
+}
+
+/// Clé de session contenant l'autorisation de réinitialisation des passkeys
+const RESET_GRANT_KEY: &str = "reset_grant";
+
+/// Autorisation de réinitialiser les passkeys d'un compte, obtenue en consommant un token de récupération.
+/// Elle est conservée côté serveur dans la session de celui qui a suivi le lien de récupération.
+#[derive(Serialize, Deserialize)]
+struct ResetGrant {
+    email: String,
+    expires_at: u64, // Timestamp UNIX d'expiration
+}
+
+/// Retourne l'email pour lequel la session détient une autorisation de réinitialisation valide
+fn reset_grant_email(session: &Session) -> Option<String> {
+    session
+        .get::<ResetGrant>(RESET_GRANT_KEY)
+        .ok()
+        .flatten()
+        .filter(|grant| unix_timestamp() < grant.expires_at)
+        .map(|grant| grant.email)
 }
 
 /// Début du processus d'enregistrement WebAuthn
-pub async fn register_begin(Json(payload): Json<serde_json::Value>) -> axum::response::Result<Json<serde_json::Value>> {
+pub async fn register_begin(
+    session: Session,
+    Json(payload): Json<serde_json::Value>,
+) -> axum::response::Result<Json<serde_json::Value>> {
     let email = payload
         .get("email")
         .and_then(Value::as_str)
         .and_then(UserEmail::try_new)
         .ok_or((StatusCode::BAD_REQUEST, "Email is required"))?;
 
-    // Ensure the user's passkey is loaded if present in the database
-    ensure_store_contains_known_user_passkey(email.as_ref()).await;
-
-    // NOTE: the way reset_mode works here introduces a security vulnerability where anyone can
-    //       reset the passkey of anyone without going through the recovery token process. This
-    //       allows anyone to steal anyone's account.
+    // Resetting the passkeys of an account requires a grant obtained through the recovery link
     let reset_mode = payload.get("reset_mode").and_then(|v| v.as_bool()).unwrap_or(false);
+    if reset_mode && reset_grant_email(&session).as_deref() != Some(email.as_ref()) {
+        return Err((StatusCode::FORBIDDEN, "Invalid registration request").into());
+    }
+
     match (reset_mode, user::exists(email.as_ref())) {
         (true, Ok(true)) => (), // If reset mode is enabled, then the use must exist
         (false, Ok(false)) => (), // If reset mode is disabled, then the user must not exist
         (_, _) => return Err((StatusCode::BAD_REQUEST, "Invalid registration request").into()), // Otherwise, it's invalid
     }
 
-    let state_id = Uuid::new_v4();
-    let (pk, registration_state) = begin_registration(email.as_ref(), email.as_ref())
-        .await
+    // Exclude every passkey already known for this user
+    let known_passkeys = user::get_passkeys(email.as_ref()).unwrap_or_default();
+
+    // An existing account keeps its user handle, a new one receives its own when it is created
+    let user_handle = if reset_mode {
+        user::user_handle(email.as_ref())
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?
+    } else {
+        Uuid::new_v4()
+    };
+
+    let (pk, state) = begin_registration(user_handle, email.as_ref(), email.as_ref(), &known_passkeys)
         .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
 
-    // Save the registration state
-    REGISTRATION_STATES
-        .write()
-        .await
-        .insert(state_id.into(), registration_state);
+    // Save the registration state, bound to this session
+    let pending = PendingRegistration {
+        email: email.as_ref().to_string(),
+        user_handle,
+        state,
+    };
+    let state_id = REGISTRATION_STATES
+        .insert(&session, pending)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
 
     Ok(Json(json!({
         "publicKey": pk,
@@ -76,7 +131,10 @@ pub async fn register_begin(Json(payload): Json<serde_json::Value>) -> axum::res
 }
 
 /// Fin du processus d'enregistrement WebAuthn
-pub async fn register_complete(Json(payload): Json<serde_json::Value>) -> axum::response::Result<StatusCode> {
+pub async fn register_complete(
+    session: Session,
+    Json(payload): Json<serde_json::Value>,
+) -> axum::response::Result<StatusCode> {
     let email = payload
         .get("email")
         .and_then(Value::as_str)
@@ -84,6 +142,9 @@ pub async fn register_complete(Json(payload): Json<serde_json::Value>) -> axum::
         .ok_or((StatusCode::BAD_REQUEST, "Email is required"))?;
 
     let reset_mode = payload.get("reset_mode").and_then(|v| v.as_bool()).unwrap_or(false);
+    if reset_mode && reset_grant_email(&session).as_deref() != Some(email.as_ref()) {
+        return Err((StatusCode::FORBIDDEN, "Invalid registration request").into());
+    }
 
     let first_name = payload
         .get("first_name")
@@ -95,6 +156,12 @@ pub async fn register_complete(Json(payload): Json<serde_json::Value>) -> axum::
         .and_then(Value::as_str)
         .and_then(TextualContent::try_new_short_form_content)
         .ok_or((StatusCode::BAD_REQUEST, "Last name is required"))?;
+    let passkey_name = payload
+        .get("passkey_name")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_PASSKEY_NAME);
+    let passkey_name = TextualContent::try_new_short_form_content(passkey_name)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid passkey name"))?;
 
     // Fetch the saved state
     let state_id = payload
@@ -102,12 +169,12 @@ pub async fn register_complete(Json(payload): Json<serde_json::Value>) -> axum::
         .and_then(Value::as_str)
         .and_then(|v| Uuid::parse_str(v).ok())
         .ok_or((StatusCode::BAD_REQUEST, "Invalid request parameters"))?;
-    let stored_state = {
-        let mut states = REGISTRATION_STATES.write().await;
-        states
-            .remove(state_id.to_string().as_str())
-            .ok_or((StatusCode::BAD_REQUEST, "Invalid registration session"))?
-    };
+    let pending = REGISTRATION_STATES
+        .take(&session, &state_id)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid registration session"))?;
+    if pending.email != email.as_ref() {
+        return Err((StatusCode::BAD_REQUEST, "Invalid registration session").into());
+    }
 
     let cred = payload
         .get("response")
@@ -115,66 +182,72 @@ pub async fn register_complete(Json(payload): Json<serde_json::Value>) -> axum::
         .ok_or((StatusCode::BAD_REQUEST, "Invalid response"))?;
 
     // Complete the registration
-    complete_registration(email.as_ref(), &cred, &stored_state)
-        .await
+    let passkey = complete_registration(&cred, &pending.state)
         .map_err(|_| (StatusCode::FORBIDDEN, "Failed to complete registration"))?;
 
-    let passkey = CREDENTIAL_STORE.read().await.get(email.as_ref()).unwrap().clone();
-
     if !reset_mode {
-        user::create(email.as_ref(), first_name.as_ref(), last_name.as_ref())
+        let created = user::create(email.as_ref(), first_name.as_ref(), last_name.as_ref(), pending.user_handle)
             .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete registration"))?;
 
-        if let Ok(verification_token) = token::generate(email.as_ref()) {
+        // Another registration may have created the account since this one started
+        if !created {
+            return Err((StatusCode::BAD_REQUEST, "Invalid registration request").into());
+        }
+
+        if let Ok(verification_token) = token::generate(email.as_ref(), token::Purpose::Validation) {
             // Send verification email
-            if let Err(_) = send_mail(
-                email.as_ref(),
-                "Verify your account",
-                &format!(
-                    "Welcome! Please verify your account by clicking this link: http://localhost:8080/validate/{}",
-                    verification_token
-                ),
-            ) {
+            if send_verification_mail(email.as_ref(), first_name.as_ref(), &verification_token)
+                .await
+                .is_err()
+            {
                 // Log error but don't fail the registration
                 error!("Failed to send verification email to {}", email.as_ref());
             }
         }
     }
 
-    user::set_passkey(email.as_ref(), passkey)
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete registration"))?;
+    if reset_mode {
+        user::reset_passkeys(email.as_ref(), passkey_name.as_ref(), passkey)
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete registration"))?;
+
+        // The grant can only be used once
+        session.remove_value(RESET_GRANT_KEY);
+    } else {
+        user::add_passkey(email.as_ref(), passkey_name.as_ref(), passkey)
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete registration"))?;
+    }
 
     Ok(StatusCode::OK)
 }
 
 /// Début du processus d'authentification WebAuthn
-pub async fn login_begin(Json(payload): Json<serde_json::Value>) -> axum::response::Result<Json<serde_json::Value>> {
+pub async fn login_begin(
+    session: Session,
+    Json(payload): Json<serde_json::Value>,
+) -> axum::response::Result<Json<serde_json::Value>> {
     let email = payload
         .get("email")
         .and_then(Value::as_str)
         .and_then(UserEmail::try_new)
         .ok_or((StatusCode::BAD_REQUEST, "Email is required"))?;
 
-    // Ensure the user's passkey is loaded if present in the database
-    ensure_store_contains_known_user_passkey(email.as_ref()).await;
-
     // Check user exists and is verified before starting authentication
-    match user::get(email.as_ref()) {
-        Some(user_data) if !user_data.verified => Err((StatusCode::BAD_REQUEST, "Invalid authentication request"))?,
-        None => Err((StatusCode::BAD_REQUEST, "Invalid authentication request"))?,
-        Some(_) => {} // User exists and is verified, continue with authentication
-    }
+    let passkeys = match user::get(email.as_ref()) {
+        Some(user_data) if user_data.verified => user_data
+            .passkeys
+            .into_iter()
+            .map(|pk| pk.passkey)
+            .collect::<Vec<_>>(),
+        _ => return Err((StatusCode::BAD_REQUEST, "Invalid authentication request").into()),
+    };
 
-    let state_id = Uuid::new_v4();
-    let (pk, state) = begin_authentication(email.as_ref())
-        .await
+    let (pk, state) = begin_authentication(&passkeys)
         .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start authentication"))?;
 
-    // Save the authn state
-    AUTHENTICATION_STATES
-        .write()
-        .await
-        .insert(state_id.into(), state);
+    // Save the authn state, bound to this session
+    let state_id = AUTHENTICATION_STATES
+        .insert(&session, (email.as_ref().to_string(), state))
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start authentication"))?;
 
     Ok(Json(json!({
         "publicKey": pk,
@@ -187,36 +260,106 @@ pub async fn login_complete(
     session: Session,
     Json(payload): Json<serde_json::Value>,
 ) -> axum::response::Result<Redirect> {
-    let response = payload.get("response").ok_or_else(|| (StatusCode::BAD_REQUEST, "Response is required"))?;
+    let response = payload.get("response").ok_or((StatusCode::BAD_REQUEST, "Response is required"))?;
     let state_id = payload.get("state_id")
         .and_then(Value::as_str)
         .and_then(|v| Uuid::parse_str(v).ok())
-        .ok_or_else(|| (StatusCode::BAD_REQUEST, "State ID is required"))?;
+        .ok_or((StatusCode::BAD_REQUEST, "State ID is required"))?;
 
     let cred: PublicKeyCredential = serde_json::from_value(response.clone())
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid response"))?;
 
     // Fetch the saved state
-    let stored_state = {
-        let mut states = AUTHENTICATION_STATES.write().await;
-        states
-            .remove(state_id.to_string().as_str())
-            .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid authentication session"))?
-    };
+    let (email, stored_state) = AUTHENTICATION_STATES
+        .take(&session, &state_id)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid authentication session"))?;
+
+    // Complete the authentication, repeated failures on an account are reported
+    let result = complete_authentication(&cred, &stored_state).map_err(|_| {
+        rate_limit::record_failed_login(&email);
+        (StatusCode::BAD_REQUEST, "Failed to complete authentication")
+    })?;
+    rate_limit::record_successful_login(&email);
+
+    // The credential that was used must belong to the account the ceremony was started for
+    let user = user::get(&email)
+        .filter(|user| user.email == email && user.has_credential(result.cred_id()))
+        .ok_or((StatusCode::BAD_REQUEST, "Failed to complete authentication"))?;
+    user::update_passkey_credential(&user.email, &result)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete authentication"))?;
+
+    open_session(&session, &user.email)?;
+    Ok(Redirect::to("/home"))
+}
 
-    // Complete the authentication
-    complete_authentication(&cred, &stored_state)
-        .await
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Failed to complete authentication"))?;
+/// Début du processus d'authentification WebAuthn sans email, avec une passkey découvrable
+pub async fn discoverable_login_begin(session: Session) -> axum::response::Result<Json<serde_json::Value>> {
+    let (pk, state) = begin_discoverable_authentication()
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start authentication"))?;
 
-    // Update the session to indicate the user is authenticated
-    session
-        .insert("authenticated", true)
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set session"))?;
+    // Save the authn state, bound to this session
+    let state_id = DISCOVERABLE_STATES
+        .insert(&session, state)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start authentication"))?;
+
+    Ok(Json(json!({
+        "publicKey": pk,
+        "state_id": state_id,
+    })))
+}
+
+/// Fin du processus d'authentification WebAuthn sans email, l'utilisateur étant retrouvé par son identifiant WebAuthn.
+/// Les passkeys enregistrées avant que cet identifiant ne soit conservé en renvoient un autre, inconnu,
+/// et ne sont utilisables qu'avec l'email : l'identifiant renvoyé n'étant pas signé, il n'est jamais adopté.
+pub async fn discoverable_login_complete(
+    session: Session,
+    Json(payload): Json<serde_json::Value>,
+) -> axum::response::Result<Redirect> {
+    let response = payload.get("response").ok_or((StatusCode::BAD_REQUEST, "Response is required"))?;
+    let state_id = payload.get("state_id")
+        .and_then(Value::as_str)
+        .and_then(|v| Uuid::parse_str(v).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "State ID is required"))?;
+
+    let cred: PublicKeyCredential = serde_json::from_value(response.clone())
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid response"))?;
 
+    // Fetch the saved state
+    let stored_state = DISCOVERABLE_STATES
+        .take(&session, &state_id)
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid authentication session"))?;
+
+    // Resolve the user from the user handle returned by the authenticator
+    let user = identify_discoverable_authentication(&cred)
+        .ok()
+        .and_then(|handle| user::find_by_user_handle(&handle))
+        .filter(|user| user.verified)
+        .ok_or((StatusCode::BAD_REQUEST, "Failed to complete authentication"))?;
+
+    // Complete the authentication against the passkeys of that user only
+    let passkeys = user.passkeys.iter().map(|pk| pk.passkey.clone()).collect::<Vec<_>>();
+    let result = complete_discoverable_authentication(&cred, stored_state, &passkeys).map_err(|_| {
+        rate_limit::record_failed_login(&user.email);
+        (StatusCode::BAD_REQUEST, "Failed to complete authentication")
+    })?;
+    rate_limit::record_successful_login(&user.email);
+
+    user::update_passkey_credential(&user.email, &result)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to complete authentication"))?;
+
+    open_session(&session, &user.email)?;
     Ok(Redirect::to("/home"))
 }
 
+/// Lie la session à l'utilisateur authentifié
+fn open_session(session: &Session, email: &str) -> Result<(), (StatusCode, &'static str)> {
+    // Rotate the session id to prevent session fixation, then bind the session to the user
+    session.cycle_id();
+    session
+        .insert(SESSION_USER_KEY, email)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set session"))
+}
+
 /// Gère la déconnexion de l'utilisateur
 pub async fn logout(session: Session) -> impl IntoResponse {
     session.delete();
@@ -225,7 +368,7 @@ pub async fn logout(session: Session) -> impl IntoResponse {
 
 /// Valide un compte utilisateur via un token
 pub async fn validate_account(Path(token): Path<String>) -> impl IntoResponse {
-    match token::consume(&token) {
+    match token::consume(&token, token::Purpose::Validation) {
         Ok(email) => match user::verify(&email) {
             Ok(_) => Redirect::to("/login?validated=true"),
             Err(_) => Redirect::to("/register?error=validation_failed"),
@@ -246,22 +389,16 @@ pub async fn recover_account(Json(payload): Json<serde_json::Value>) -> axum::re
         // The user needs to have verified their email
         Some(user) if user.verified => {
             // Generate recovery token
-            let recovery_token = token::generate(email.as_ref())
+            let recovery_token = token::generate(email.as_ref(), token::Purpose::Recovery)
                 .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error."))?;
 
-            // Send recovery email
-            let recovery_link = format!("http://localhost:8080/recover/{}", recovery_token);
-            if let Err(_) = send_mail(
-                email.as_ref(),
-                "Account Recovery",
-                &format!(
-                    "Click the following link to recover your account: {}\n\n\
-                     If you did not request this recovery, you can safely ignore this email.",
-                    recovery_link
-                ),
-            ) {
-                error!("Failed to send recovery email to {}", email.as_ref());
-            }
+            // Send recovery email in the background so that the response time does not reveal
+            // whether the account exists
+            tokio::spawn(async move {
+                if send_recovery_mail(email.as_ref(), &recovery_token).await.is_err() {
+                    error!("Failed to send recovery email to {}", email.as_ref());
+                }
+            });
         }
         _ => (),
     }
@@ -273,27 +410,27 @@ pub async fn recover_account(Json(payload): Json<serde_json::Value>) -> axum::re
         .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.").into())
 }
 
-/// Gère la réinitialisation du compte utilisateur via un token de récupération
-pub async fn reset_account(Path(token): Path<String>) -> Html<String> {
-    match token::consume(&token) {
-        Ok(email) => {
-            let redirect_url = format!("/register?reset_mode=true&email={}&success=true", email);
-            Html(format!("<meta http-equiv='refresh' content='0;url={}'/>", redirect_url))
-        }
-        Err(_) => {
-            let redirect_url = "/register?error=recovery_failed";
-            Html(format!("<meta http-equiv='refresh' content='0;url={}'/>", redirect_url))
-        }
-    }
+/// Gère la réinitialisation du compte utilisateur via un token de récupération.
+/// Le token est échangé contre une autorisation de réinitialisation de courte durée liée à la session.
+pub async fn reset_account(session: Session, Path(token): Path<String>) -> Html<String> {
+    let grant = token::consume(&token, token::Purpose::Recovery).map(|email| ResetGrant {
+        email,
+        expires_at: unix_timestamp() + consts::RESET_GRANT_TTL_SECS,
+    });
+
+    let redirect_url = match grant.map(|grant| session.insert(RESET_GRANT_KEY, grant)) {
+        Ok(Ok(())) => "/register?success=true",
+        _ => "/register?error=recovery_failed",
+    };
+    Html(format!("<meta http-equiv='refresh' content='0;url={}'/>", redirect_url))
 }
 
 /// --- Affichage des pages ---
 ///
 /// Affiche la page d'accueil
-pub async fn index(session: tower_sessions::Session) -> impl IntoResponse {
-    let is_logged_in = session.get::<bool>("authenticated").unwrap_or_default().is_some();
-    let mut data = HashMap::new();
-    data.insert("authenticated", is_logged_in);
+pub async fn index(session: tower_sessions::Session, Extension(CspNonce(nonce)): Extension<CspNonce>) -> impl IntoResponse {
+    let is_logged_in = session.get::<String>(SESSION_USER_KEY).unwrap_or_default().is_some();
+    let data = json!({ "authenticated": is_logged_in, "csp_nonce": nonce });
 
     HBS.render("index", &data)
         .map(Html)
@@ -301,21 +438,30 @@ pub async fn index(session: tower_sessions::Session) -> impl IntoResponse {
 }
 
 /// Affiche la page de connexion
-pub async fn login_page() -> impl IntoResponse {
-    Html(include_str!("../../templates/login.hbs"))
+pub async fn login_page(session: Session, Extension(CspNonce(nonce)): Extension<CspNonce>) -> impl IntoResponse {
+    HBS.render("login", &json!({ "csrf_token": csrf::token(&session), "csp_nonce": nonce }))
+        .map(Html)
+        .unwrap_or_else(|_| Html("<h1>Internal Server Error</h1>".to_string()))
 }
 
 /// Affiche la page d'inscription avec des messages contextuels si présents
-pub async fn register_page(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+pub async fn register_page(
+    session: Session,
+    Extension(CspNonce(nonce)): Extension<CspNonce>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
     let mut context = HashMap::new();
-    if let Some(success) = params.get("success") {
-        if success == "true" {
-            context.insert("success_message", "Account recovery successful. Please reset your passkey.");
+    context.insert("csrf_token", csrf::token(&session));
+    context.insert("csp_nonce", nonce);
+    if let Some(email) = reset_grant_email(&session) {
+        context.insert("reset_email", email);
+        if params.get("success").is_some_and(|success| success == "true") {
+            context.insert("success_message", "Account recovery successful. Please reset your passkey.".to_string());
         }
     }
     if let Some(error) = params.get("error") {
         if error == "recovery_failed" {
-            context.insert("error_message", "Invalid or expired recovery link. Please try again.");
+            context.insert("error_message", "Invalid or expired recovery link. Please try again.".to_string());
         }
     }
 
@@ -325,6 +471,8 @@ pub async fn register_page(Query(params): Query<HashMap<String, String>>) -> imp
 }
 
 /// Affiche la page de récupération de compte
-pub async fn recover_page() -> impl IntoResponse {
-    Html(include_str!("../../templates/recover.hbs"))
+pub async fn recover_page(session: Session, Extension(CspNonce(nonce)): Extension<CspNonce>) -> impl IntoResponse {
+    HBS.render("recover", &json!({ "csrf_token": csrf::token(&session), "csp_nonce": nonce }))
+        .map(Html)
+        .unwrap_or_else(|_| Html("<h1>Internal Server Error</h1>".to_string()))
 }
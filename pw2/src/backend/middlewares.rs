@@ -1,12 +1,16 @@
 //! Middleware pour gérer les sessions utilisateur.
 //! Vérifie la validité d'une session utilisateur et rejette les requêtes non autorisées.
 
+use crate::database::user::{self, User};
 use axum::extract::FromRequestParts;
 use axum::http::{request::Parts, StatusCode};
 use tower_sessions::Session;
 
-/// Middleware pour valider une session utilisateur
-pub struct SessionUser;
+/// Clé de session contenant l'email de l'utilisateur authentifié
+pub const SESSION_USER_KEY: &str = "user_email";
+
+/// Middleware pour valider une session utilisateur, donne accès à l'utilisateur connecté
+pub struct SessionUser(pub User);
 
 #[async_trait::async_trait]
 impl<S> FromRequestParts<S> for SessionUser
@@ -17,9 +21,12 @@ where
 
     async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
         if let Some(session) = parts.extensions.get::<Session>() {
-            // NOTE: fixed to make it work, before it was returning true for everyone.
-            if session.get::<bool>("authenticated").unwrap_or_default().is_some() {
-                return Ok(SessionUser);
+            if let Ok(Some(email)) = session.get::<String>(SESSION_USER_KEY) {
+                // The user may have been removed since the session was created
+                if let Some(user) = user::get(&email) {
+                    return Ok(SessionUser(user));
+                }
+                session.flush();
             }
         }
 
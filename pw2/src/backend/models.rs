@@ -1,11 +1,218 @@
 //! Définitions des structures pour les interactions avec l'API.
-//! Contient les structures pour l'enregistrement, l'authentification et la récupération.
+//! Contient les structures pour l'enregistrement, l'authentification, la récupération et l'affichage des posts.
 
-use serde::Serialize;
+use crate::consts;
+use crate::database::post::{Comment, FeedCursor, FeedQuery, FeedSort, Post, Reaction};
+use crate::database::user::{self, User, UserPasskey};
+use crate::utils::markdown;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-/// Structure pour représenter les réponses aux défis WebAuthn
+/// Structure pour représenter une passkey d'un utilisateur sans exposer sa clé publique
 #[derive(Serialize)]
-pub struct WebAuthnChallenge {
-    pub challenge: serde_json::Value, // Données du défi
-    pub state_id: String,            // Identifiant d'état du défi
-}
\ No newline at end of file
+pub struct PasskeySummary {
+    pub id: String,   // Identifiant de la passkey (base64url)
+    pub name: String, // Nom donné par l'utilisateur
+}
+
+impl From<&UserPasskey> for PasskeySummary {
+    fn from(passkey: &UserPasskey) -> Self {
+        Self {
+            id: serde_json::to_value(passkey.passkey.cred_id())
+                .ok()
+                .and_then(|id| id.as_str().map(str::to_owned))
+                .unwrap_or_default(),
+            name: passkey.name.clone(),
+        }
+    }
+}
+
+/// Structure pour afficher un post dans le fil, avec le nom de son auteur
+#[derive(Serialize)]
+pub struct PostView {
+    pub id: String,
+    pub author: Option<String>, // Identifiant WebAuthn de l'auteur, utilisé pour filtrer le fil sans exposer son email
+    pub author_name: String,    // Prénom et nom de l'auteur
+    pub is_own: bool,        // Le post appartient à l'utilisateur connecté
+    pub edited: bool,
+    pub content: String,                // Source du contenu, utilisée pour la modification
+    pub content_html: String,           // Contenu mis en forme, déjà nettoyé
+    pub image_path: Option<String>,     // Plus grande version de l'image
+    pub thumbnail_path: Option<String>, // Plus petite version de l'image
+    pub image_srcset: Option<String>,   // Toutes les versions, au format de l'attribut `srcset`
+    #[serde(flatten)]
+    pub reactions: ReactionSummary,
+    pub comments: Vec<CommentView>, // Commentaires de premier niveau, avec leurs réponses
+}
+
+/// Structure pour afficher un commentaire, avec le nom de son auteur
+#[derive(Serialize)]
+pub struct CommentView {
+    pub id: String,
+    pub author_name: String,
+    pub is_own: bool,
+    pub content: String,
+    pub replies: Vec<CommentView>,
+}
+
+impl CommentView {
+    pub fn new(comment: &Comment, author: Option<&User>, viewer: &User) -> Self {
+        Self {
+            id: comment.id.to_string(),
+            author_name: display_name(author),
+            is_own: comment.author == viewer.email,
+            content: comment.content.clone(),
+            replies: Vec::new(),
+        }
+    }
+}
+
+/// Prénom et nom d'un auteur
+fn display_name(author: Option<&User>) -> String {
+    author
+        .map(|author| format!("{} {}", author.first_name, author.last_name))
+        .unwrap_or_else(|| "Unknown author".to_string())
+}
+
+/// Compteurs de réactions d'un post et réaction de l'utilisateur connecté
+#[derive(Serialize)]
+pub struct ReactionSummary {
+    pub likes: usize,
+    pub dislikes: usize,
+    pub own_reaction: Option<Reaction>,
+}
+
+impl ReactionSummary {
+    pub fn new(post: &Post, viewer: &User) -> Self {
+        Self {
+            likes: post.count(Reaction::Like),
+            dislikes: post.count(Reaction::Dislike),
+            own_reaction: post.reaction_of(&viewer.email),
+        }
+    }
+}
+
+impl PostView {
+    /// Construit la vue d'un post, `authors` permettant de retrouver l'auteur d'un post ou d'un commentaire
+    pub fn new(post: Post, viewer: &User, mut authors: impl FnMut(&str) -> Option<User>) -> Self {
+        let reactions = ReactionSummary::new(&post, viewer);
+
+        // Les réponses sont rattachées à leur commentaire parent
+        let mut comments: Vec<CommentView> = Vec::new();
+        for comment in post.comments.iter().filter(|comment| comment.parent_id.is_none()) {
+            let mut view = CommentView::new(comment, authors(&comment.author).as_ref(), viewer);
+            view.replies = post
+                .comments
+                .iter()
+                .filter(|reply| reply.parent_id == Some(comment.id))
+                .map(|reply| CommentView::new(reply, authors(&reply.author).as_ref(), viewer))
+                .collect();
+            comments.push(view);
+        }
+
+        let thumbnail_path = post
+            .image_variants
+            .first()
+            .map(|variant| variant.path.clone())
+            .or_else(|| post.image_path.clone());
+        let image_srcset = (!post.image_variants.is_empty()).then(|| {
+            post.image_variants
+                .iter()
+                .map(|variant| format!("{} {}w", variant.path, variant.width))
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+
+        let author = authors(&post.author);
+        Self {
+            id: post.id.to_string(),
+            author_name: display_name(author.as_ref()),
+            author: author.and_then(|author| author.user_handle).map(|handle| handle.to_string()),
+            is_own: !post.author.is_empty() && post.author == viewer.email,
+            edited: post.edited_at.is_some(),
+            content_html: markdown::render(&post.content),
+            content: post.content,
+            image_path: post.image_path,
+            thumbnail_path,
+            image_srcset,
+            reactions,
+            comments,
+        }
+    }
+}
+
+/// Paramètres de pagination et de filtre du fil
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FeedParams {
+    #[serde(default)]
+    pub sort: FeedSort,
+    pub author: Option<String>, // Identifiant WebAuthn de l'auteur
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl FeedParams {
+    /// Requête correspondante, l'auteur étant retrouvé à partir de son identifiant
+    pub fn to_query(&self) -> Result<FeedQuery, (axum::http::StatusCode, &'static str)> {
+        let after = self
+            .cursor
+            .as_deref()
+            .filter(|cursor| !cursor.is_empty())
+            .map(str::parse::<FeedCursor>)
+            .transpose()
+            .map_err(|_| (axum::http::StatusCode::BAD_REQUEST, "Invalid cursor"))?;
+
+        let author = self
+            .author
+            .as_deref()
+            .filter(|author| !author.is_empty())
+            .map(|author| {
+                Uuid::parse_str(author)
+                    .ok()
+                    .and_then(|handle| user::find_by_user_handle(&handle))
+                    .map(|author| author.email)
+                    .ok_or((axum::http::StatusCode::BAD_REQUEST, "Invalid author"))
+            })
+            .transpose()?;
+
+        Ok(FeedQuery {
+            sort: self.sort,
+            author,
+            after,
+            limit: self.limit.unwrap_or(consts::FEED_PAGE_SIZE).clamp(1, consts::FEED_MAX_PAGE_SIZE),
+        })
+    }
+
+    /// Lien vers la page d'accueil avec ces paramètres
+    pub fn home_link(&self) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(sort) = serde_json::to_value(self.sort).ok().as_ref().and_then(|sort| sort.as_str()) {
+            query.append_pair("sort", sort);
+        }
+        if let Some(author) = self.author.as_deref().filter(|author| !author.is_empty()) {
+            query.append_pair("author", author);
+        }
+        if let Some(cursor) = &self.cursor {
+            query.append_pair("cursor", cursor);
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+        format!("/home?{}", query.finish())
+    }
+
+    /// Paramètres de la page suivante
+    pub fn next(&self, cursor: FeedCursor) -> Self {
+        Self {
+            cursor: Some(cursor.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Page du fil retournée par l'API
+#[derive(Serialize)]
+pub struct FeedPage {
+    pub posts: Vec<PostView>,
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,217 @@
+//! Limitation du nombre d'appels aux routes d'authentification et de récupération.
+//! Chaque adresse IP et chaque email dispose d'un seau de jetons, une requête consomme un jeton
+//! et les jetons se rechargent au fil du temps. Les échecs d'authentification répétés sur un même
+//! compte sont signalés dans les logs.
+
+use crate::config::{self, RateLimitConfig};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::{error, warn};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Taille maximale d'un corps de requête dont l'email est extrait
+const MAX_BODY_BYTES: usize = 16 * 1024;
+
+/// Nombre de clés suivies au-delà duquel les seaux pleins sont oubliés
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Nombre d'échecs d'authentification consécutifs par email
+static FAILED_LOGINS: Lazy<Mutex<HashMap<String, u32>>> = Lazy::new(Default::default);
+
+/// Seau de jetons d'une clé
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Seaux de jetons partageant la même capacité et la même vitesse de recharge
+#[derive(Debug)]
+struct Buckets {
+    capacity: u32,
+    refill: Duration, // Délai de recharge d'un jeton
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Buckets {
+    fn new(capacity: u32, refill: Duration) -> Self {
+        Self {
+            capacity,
+            refill,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Consomme un jeton pour la clé donnée, retourne le délai avant le prochain jeton si le seau est vide
+    fn take(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.capacity);
+        let refill = self.refill.as_secs_f64();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Les seaux pleins sont équivalents à des seaux absents
+        if buckets.len() >= MAX_TRACKED_KEYS {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() / refill < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed / refill).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * refill))
+        }
+    }
+}
+
+/// Limiteur partagé par les routes protégées
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_ip: Buckets,
+    per_email: Buckets,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Arc<Self> {
+        Arc::new(Self {
+            per_ip: Buckets::new(config.per_ip_burst, Duration::from_secs(config.per_ip_refill_secs)),
+            per_email: Buckets::new(config.per_email_burst, Duration::from_secs(config.per_email_refill_secs)),
+            trust_forwarded_for: config.trust_forwarded_for,
+        })
+    }
+
+    /// Adresse IP du client, donnée par le reverse proxy si la configuration lui fait confiance
+    fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| forwarded_for(request.headers()))
+            .flatten();
+        forwarded.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        })
+    }
+}
+
+/// Dernière adresse de l'en-tête `X-Forwarded-For`, ajoutée par le reverse proxy
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .last()
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+/// Middleware limitant les requêtes par adresse IP, puis par email pour les requêtes JSON qui en contiennent un
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    let now = Instant::now();
+
+    if let Some(ip) = limiter.client_ip(&request) {
+        if let Err(retry_after) = limiter.per_ip.take(&ip.to_string(), now) {
+            warn!("Rate limit reached for {} on {}", ip, request.uri().path());
+            return too_many_requests(retry_after);
+        }
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_BODY_BYTES).await else {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Request too large").into_response();
+    };
+    let email = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|payload| payload.get("email").and_then(Value::as_str).map(|email| email.trim().to_lowercase()));
+    if let Some(email) = email.filter(|email| !email.is_empty()) {
+        if let Err(retry_after) = limiter.per_email.take(&email, now) {
+            warn!("Rate limit reached for {} on {}", email, parts.uri.path());
+            return too_many_requests(retry_after);
+        }
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+/// Réponse `429`, indiquant en secondes quand réessayer
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.to_string())],
+        "Too many requests, please try again later",
+    )
+        .into_response()
+}
+
+/// Enregistre un échec d'authentification pour un compte, et alerte lorsque les échecs se répètent
+pub fn record_failed_login(email: &str) {
+    let threshold = config::get().rate_limit.failed_login_alert_threshold;
+    let mut failures = FAILED_LOGINS.lock().unwrap_or_else(PoisonError::into_inner);
+    let count = failures.entry(email.to_string()).or_default();
+    *count += 1;
+    if count.is_multiple_of(threshold) {
+        error!("ALERT: {} consecutive failed authentications for {}", count, email);
+    }
+}
+
+/// Remet à zéro les échecs d'authentification d'un compte après une authentification réussie
+pub fn record_successful_login(email: &str) {
+    FAILED_LOGINS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(email);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_refill_over_time() {
+        let buckets = Buckets::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(buckets.take("a", start).is_ok());
+        assert!(buckets.take("a", start).is_ok());
+        assert_eq!(buckets.take("a", start), Err(Duration::from_secs(10)));
+
+        // Other keys have their own bucket
+        assert!(buckets.take("b", start).is_ok());
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(buckets.take("a", later), Err(Duration::from_secs(5)));
+        assert!(buckets.take("a", later + Duration::from_secs(5)).is_ok());
+        assert!(buckets.take("a", later + Duration::from_secs(5)).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_for_uses_the_address_added_by_the_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.1, 198.51.100.7".parse().unwrap());
+        assert_eq!(forwarded_for(&headers), Some("198.51.100.7".parse().unwrap()));
+        headers.insert("x-forwarded-for", "not an ip".parse().unwrap());
+        assert_eq!(forwarded_for(&headers), None);
+    }
+}
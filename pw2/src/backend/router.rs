@@ -1,35 +1,57 @@
 //! Configuration des routes pour l'application.
 //! Définit les routes accessibles avec ou sans authentification et configure les middlewares.
 
-use crate::backend::handlers_auth::{create_post, home, like_post};
+use crate::backend::handlers_auth::{
+    add_comment, create_post, delete_comment, delete_post, edit_post, feed, home, like_post,
+    passkey_register_begin, passkey_register_complete, passkey_rename, passkey_revoke, passkeys_page, serve_upload,
+};
 use crate::backend::handlers_unauth::{
-    index, login_begin, login_complete, login_page, logout, recover_account, recover_page,
-    register_begin, register_complete, register_page, reset_account, validate_account,
+    discoverable_login_begin, discoverable_login_complete, index, login_begin, login_complete, login_page, logout,
+    recover_account, recover_page, register_begin, register_complete, register_page, reset_account, validate_account,
 };
-use crate::consts;
+use crate::backend::handlers_dev::{mail_delete, mail_inbox, mail_view};
+use crate::backend::{csrf, rate_limit, security_headers};
+use crate::backend::session_store::SessionBackend;
+use crate::{config, consts};
 use axum::error_handling::HandleErrorLayer;
+use axum::extract::DefaultBodyLimit;
 use axum::{routing::{get, post}, BoxError, Router};
 use http::StatusCode;
 use tower::ServiceBuilder;
-use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
-use tower_sessions::{MemoryStore, SessionManagerLayer};
+use tower_sessions::{Expiry, SessionManagerLayer};
 
 /// Initialisation du routeur principal et des middlewares
-pub fn get_router() -> Router {
-    // Configuration CORS pour permettre les requêtes de n'importe quelle origine (en mode debug uniquement)
+pub fn get_router(store: SessionBackend) -> Router {
+    with_middlewares(routes(), store)
+}
+
+/// Ensemble des routes de l'application
+fn routes() -> Router {
+    // Boîte de réception des emails simulés (en mode debug uniquement)
     let router = if cfg!(debug_assertions) {
-        let cors = CorsLayer::new()
-            .allow_methods(tower_http::cors::AllowMethods::any())
-            .allow_origin(Any);
-        Router::new().layer(cors)
+        dev_routes()
     } else {
         Router::new()
     };
 
-    // Configuration des sessions en mémoire
-    let store = MemoryStore::default(); // Initialisation du MemoryStore
-    let session_manager = SessionManagerLayer::new(store).with_http_only(true);
+    router
+        .merge(unauth_routes())
+        .merge(rate_limited_routes())
+        .merge(auth_routes())
+}
+
+/// Ajoute les middlewares communs à toutes les routes
+fn with_middlewares(router: Router, store: SessionBackend) -> Router {
+    // Configuration des sessions, expirées après une période d'inactivité
+    // Le cookie n'est envoyé qu'en HTTPS lorsque l'application est servie en HTTPS
+    let config = config::get();
+    let session_manager = SessionManagerLayer::new(store)
+        .with_http_only(true)
+        .with_secure(config.public_base_url.scheme() == "https")
+        .with_expiry(Expiry::OnInactivity(time::Duration::seconds(
+            config.session.inactivity_timeout_secs,
+        )));
 
     let service = ServiceBuilder::new()
         .layer(HandleErrorLayer::new(|_e: BoxError| async move {
@@ -37,10 +59,11 @@ pub fn get_router() -> Router {
         }))
         .layer(session_manager);
 
+    // Les requêtes modifiant l'état doivent provenir de l'application et contenir le token CSRF
     router
-        .merge(unauth_routes())
-        .merge(auth_routes())
+        .layer(axum::middleware::from_fn(csrf::protect))
         .layer(service)
+        .layer(axum::middleware::from_fn(security_headers::add_security_headers)) // En-têtes de sécurité et nonce CSP
 }
 
 /// Routes accessibles sans authentification
@@ -48,21 +71,1081 @@ fn unauth_routes() -> Router {
     Router::new()
         .route("/", get(index)) // Page d'accueil
         .route("/validate/:token", get(validate_account)) // Validation d'un compte
-        .route("/register", get(register_page).post(register_begin)) // Début de l'enregistrement WebAuthn
+        .route("/register", get(register_page)) // Page d'inscription
         .route("/register/complete", post(register_complete)) // Fin de l'enregistrement WebAuthn
-        .route("/login", get(login_page).post(login_begin)) // Page de connexion
+        .route("/login", get(login_page)) // Page de connexion
         .route("/login/complete", post(login_complete)) // Fin de l'authentification WebAuthn
+        .route("/login/discoverable/complete", post(discoverable_login_complete)) // Fin de l'authentification sans email
         .route("/logout", get(logout)) // Déconnexion
-        .route("/recover", get(recover_page).post(recover_account)) // Page et handler de récupération
+        .route("/recover", get(recover_page)) // Page de récupération
         .route("/recover/:token", get(reset_account)) // Lien pour la récupération de compte
+        .nest_service("/static", ServeDir::new(consts::STATIC_DIR)) // Scripts et feuilles de style
+}
+
+/// Routes sans authentification créant un état côté serveur ou envoyant un email,
+/// limitées par adresse IP et par email
+fn rate_limited_routes() -> Router {
+    let limiter = rate_limit::RateLimiter::new(&config::get().rate_limit);
+    Router::new()
+        .route("/register", post(register_begin)) // Début de l'enregistrement WebAuthn
+        .route("/login", post(login_begin)) // Début de l'authentification WebAuthn
+        .route("/login/discoverable", post(discoverable_login_begin)) // Début de l'authentification sans email
+        .route("/recover", post(recover_account)) // Envoi d'un lien de récupération
+        .route_layer(axum::middleware::from_fn_with_state(limiter, rate_limit::limit))
+}
+
+/// Routes de développement
+fn dev_routes() -> Router {
+    Router::new()
+        .route("/dev/mail", get(mail_inbox)) // Liste des emails envoyés
+        .route("/dev/mail/:pk", get(mail_view)) // Affichage d'un email
+        .route("/dev/mail/:pk/delete", post(mail_delete)) // Suppression d'un email
 }
 
 /// Routes nécessitant une authentification
 fn auth_routes() -> Router {
     Router::new()
         .route("/home", get(home)) // Page principale
+        .route("/feed", get(feed)) // Fil des posts en JSON, paginé
         .route("/post/like", post(like_post)) // Ajout d'un like à un post
-        .route("/post/create", post(create_post)) // Ajout d'un post
-        .nest_service("/uploads", ServeDir::new(consts::UPLOADS_DIR))
+        .route(
+            "/post/create",
+            post(create_post).layer(DefaultBodyLimit::max(config::get().uploads.max_request_bytes)),
+        ) // Ajout d'un post, taille limitée par la configuration
+        .route("/post/edit", post(edit_post)) // Modification d'un post par son auteur
+        .route("/post/delete", post(delete_post)) // Suppression d'un post par son auteur
+        .route("/post/comment", post(add_comment)) // Ajout d'un commentaire ou d'une réponse
+        .route("/post/comment/delete", post(delete_comment)) // Suppression d'un commentaire par son auteur
+        .route("/passkeys", get(passkeys_page)) // Liste des passkeys de l'utilisateur
+        .route("/passkeys/register", post(passkey_register_begin)) // Début de l'ajout d'une passkey
+        .route("/passkeys/register/complete", post(passkey_register_complete)) // Fin de l'ajout d'une passkey
+        .route("/passkeys/rename", post(passkey_rename)) // Renommage d'une passkey
+        .route("/passkeys/revoke", post(passkey_revoke)) // Révocation d'une passkey
+        .route("/uploads/:name", get(serve_upload)) // Images des posts, servies aux utilisateurs connectés
         .layer(axum::middleware::from_extractor::<crate::backend::middlewares::SessionUser>()) // Middleware pour vérifier l'utilisateur connecté
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::middlewares::SESSION_USER_KEY;
+    use crate::database::post::{self, Reaction};
+    use crate::database::{self, email, token, user};
+    use crate::HBS;
+    use axum::{body::Body, extract::Path, Extension};
+    use http::{header, Request, Response};
+    use openssl::{bn::{BigNum, BigNumContext}, ec::{EcGroup, EcKey}, ecdsa::EcdsaSig, nid::Nid, pkey::Private, sha::sha256};
+    use serde_json::{json, Value};
+    use std::{cell::Cell, sync::Arc};
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, Session};
+    use uuid::Uuid;
+    use webauthn_rs::prelude::{Base64UrlSafeData, Passkey};
+
+    fn app() -> Router {
+        let routes = routes()
+            .route("/test/login/:email", get(test_login))
+            .route("/test/csrf", get(|session: Session| async move { csrf::token(&session) }));
+        with_middlewares(routes, SessionBackend::Memory(MemoryStore::default())).layer(Extension(Arc::new(HBS.clone())))
+    }
+
+    /// Connecte directement un utilisateur, sans passer par WebAuthn
+    async fn test_login(session: Session, Path(email): Path<String>) -> StatusCode {
+        session.cycle_id();
+        session.insert(SESSION_USER_KEY, email).unwrap();
+        StatusCode::OK
+    }
+
+    /// Retourne le cookie de session d'un utilisateur connecté
+    async fn login(app: &Router, email: &str) -> String {
+        let request = Request::get(format!("/test/login/{}", email)).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        session_cookie(&response).expect("No session cookie set")
+    }
+
+    /// Prépare une requête POST de l'application : le cookie de session, créé si nécessaire,
+    /// et le token CSRF de cette session sont ajoutés
+    async fn same_site_post(app: &Router, uri: &str, cookie: Option<&str>) -> http::request::Builder {
+        let mut request = Request::get("/test/csrf");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let cookie = session_cookie(&response)
+            .or(cookie.map(str::to_owned))
+            .expect("No session cookie set");
+        let token = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        Request::post(uri)
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::COOKIE, cookie)
+            .header(csrf::CSRF_HEADER, String::from_utf8(token.to_vec()).unwrap())
+    }
+
+    /// Envoie une requête préparée avec un corps JSON, retourne le statut et le corps de la réponse
+    async fn send_json(app: &Router, request: http::request::Builder, body: Value) -> (StatusCode, String) {
+        let request = request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Crée un utilisateur vérifié avec une adresse unique
+    fn verified_user() -> String {
+        let email = format!("{}@example.com", Uuid::new_v4());
+        user::create(&email, "Jane", "Doe", Uuid::new_v4()).unwrap();
+        user::verify(&email).unwrap();
+        email
+    }
+
+    /// Crée une passkey ES256 dont seul l'identifiant importe
+    fn passkey(cred_id: &[u8]) -> Passkey {
+        serde_json::from_value(json!({
+            "cred": {
+                "cred_id": Base64UrlSafeData::from(cred_id.to_vec()),
+                "cred": {
+                    "type_": "ES256",
+                    "key": {
+                        "EC_EC2": {
+                            "curve": "SECP256R1",
+                            "x": Base64UrlSafeData::from(vec![1; 32]),
+                            "y": Base64UrlSafeData::from(vec![2; 32]),
+                        },
+                    },
+                },
+                "counter": 0,
+                "transports": null,
+                "user_verified": true,
+                "backup_eligible": false,
+                "backup_state": false,
+                "registration_policy": "required",
+                "extensions": {
+                    "cred_protect": "NotRequested",
+                    "hmac_create_secret": "NotRequested",
+                    "appid": "NotRequested",
+                    "cred_props": "NotRequested",
+                },
+                "attestation": { "data": "None", "metadata": "None" },
+                "attestation_format": "none",
+            }
+        }))
+        .unwrap()
+    }
+
+    /// Crée un utilisateur vérifié possédant une passkey par identifiant donné
+    fn user_with_passkeys(cred_ids: &[&[u8]]) -> String {
+        let email = verified_user();
+        for (i, cred_id) in cred_ids.iter().enumerate() {
+            user::add_passkey(&email, &format!("Key {}", i + 1), passkey(cred_id)).unwrap();
+        }
+        email
+    }
+
+    /// Authentificateur logiciel, produit des réponses WebAuthn valides pour la Relying Party de test
+    struct TestAuthenticator {
+        cred_id: Vec<u8>,
+        key: EcKey<Private>,
+        counter: Cell<u32>, // Compteur de signatures, incrémenté à chaque authentification
+    }
+
+    impl TestAuthenticator {
+        fn new() -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            Self {
+                cred_id: Uuid::new_v4().as_bytes().to_vec(),
+                key: EcKey::generate(&group).unwrap(),
+                counter: Cell::new(0),
+            }
+        }
+
+        /// Coordonnées de la clé publique
+        fn public_key(&self) -> (Vec<u8>, Vec<u8>) {
+            let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+            self.key
+                .public_key()
+                .affine_coordinates(self.key.group(), &mut x, &mut y, &mut BigNumContext::new().unwrap())
+                .unwrap();
+            (x.to_vec_padded(32).unwrap(), y.to_vec_padded(32).unwrap())
+        }
+
+        fn client_data(kind: &str, options: &Value) -> Vec<u8> {
+            json!({
+                "type": kind,
+                "challenge": options["publicKey"]["challenge"],
+                "origin": "http://localhost:8080",
+                "crossOrigin": false,
+            })
+            .to_string()
+            .into_bytes()
+        }
+
+        fn authenticator_data(flags: u8, counter: u32) -> Vec<u8> {
+            let mut data = sha256(config::get().rp_id().as_bytes()).to_vec();
+            data.push(flags);
+            data.extend(counter.to_be_bytes());
+            data
+        }
+
+        /// Répond à des options d'enregistrement, sans attestation
+        fn register(&self, options: &Value) -> Value {
+            let (x, y) = self.public_key();
+
+            // User present, user verified and attested credential data
+            let mut auth_data = Self::authenticator_data(0x45, 0);
+            auth_data.extend([0; 16]);
+            auth_data.extend((self.cred_id.len() as u16).to_be_bytes());
+            auth_data.extend(&self.cred_id);
+            // COSE key {1: 2 (EC2), 3: -7 (ES256), -1: 1 (P-256), -2: x, -3: y}
+            auth_data.extend([0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21, 0x58, 0x20]);
+            auth_data.extend(x);
+            auth_data.extend([0x22, 0x58, 0x20]);
+            auth_data.extend(y);
+
+            // {"fmt": "none", "attStmt": {}, "authData": auth_data}
+            let mut attestation = vec![0xa3, 0x63];
+            attestation.extend(b"fmt");
+            attestation.push(0x64);
+            attestation.extend(b"none");
+            attestation.push(0x67);
+            attestation.extend(b"attStmt");
+            attestation.extend([0xa0, 0x68]);
+            attestation.extend(b"authData");
+            attestation.extend([0x58, auth_data.len() as u8]);
+            attestation.extend(auth_data);
+
+            json!({
+                "id": Base64UrlSafeData::from(self.cred_id.clone()),
+                "rawId": Base64UrlSafeData::from(self.cred_id.clone()),
+                "type": "public-key",
+                "extensions": {},
+                "response": {
+                    "attestationObject": Base64UrlSafeData::from(attestation),
+                    "clientDataJSON": Base64UrlSafeData::from(Self::client_data("webauthn.create", options)),
+                },
+            })
+        }
+
+        /// Répond à des options d'authentification, en renvoyant l'identifiant WebAuthn donné
+        fn authenticate(&self, options: &Value, user_handle: Option<&[u8]>) -> Value {
+            self.counter.set(self.counter.get() + 1);
+            let auth_data = Self::authenticator_data(0x05, self.counter.get());
+            let client_data = Self::client_data("webauthn.get", options);
+            let mut signed = auth_data.clone();
+            signed.extend(sha256(&client_data));
+            let signature = EcdsaSig::sign(&sha256(&signed), &self.key).unwrap().to_der().unwrap();
+
+            json!({
+                "id": Base64UrlSafeData::from(self.cred_id.clone()),
+                "rawId": Base64UrlSafeData::from(self.cred_id.clone()),
+                "type": "public-key",
+                "extensions": {},
+                "response": {
+                    "authenticatorData": Base64UrlSafeData::from(auth_data),
+                    "clientDataJSON": Base64UrlSafeData::from(client_data),
+                    "signature": Base64UrlSafeData::from(signature),
+                    "userHandle": user_handle.map(|handle| Base64UrlSafeData::from(handle.to_vec())),
+                },
+            })
+        }
+    }
+
+    /// Démarre un enregistrement dans une nouvelle session, retourne son cookie et les options obtenues
+    async fn start_registration(app: &Router, email: &str) -> (String, Value) {
+        let request = same_site_post(app, "/register", None).await;
+        let cookie = request.headers_ref().unwrap()[header::COOKIE].to_str().unwrap().to_string();
+        let (status, body) = send_json(app, request, json!({ "email": email })).await;
+        assert_eq!(status, StatusCode::OK);
+        (cookie, serde_json::from_str(&body).unwrap())
+    }
+
+    /// Termine l'enregistrement d'une session avec une passkey de l'authentificateur
+    async fn finish_registration(
+        app: &Router,
+        cookie: &str,
+        email: &str,
+        options: &Value,
+        authenticator: &TestAuthenticator,
+    ) -> (StatusCode, String) {
+        let body = json!({
+            "email": email,
+            "first_name": "Jane",
+            "last_name": "Doe",
+            "state_id": options["state_id"],
+            "response": authenticator.register(options),
+        });
+        let request = same_site_post(app, "/register/complete", Some(cookie)).await;
+        send_json(app, request, body).await
+    }
+
+    fn session_cookie(response: &Response<Body>) -> Option<String> {
+        response
+            .headers()
+            .get(header::SET_COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(str::to_owned)
+    }
+
+    /// Suit un lien de récupération et retourne le cookie de session obtenu
+    async fn follow_recovery_link(app: &Router, token: &str) -> Option<String> {
+        let request = Request::get(format!("/recover/{}", token)).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        session_cookie(&response)
+    }
+
+    async fn post_json(app: &Router, uri: &str, body: Value, cookie: Option<&str>) -> StatusCode {
+        let request = same_site_post(app, uri, cookie)
+            .await
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_passkeys_can_be_listed_and_renamed() {
+        let app = app();
+        let email = user_with_passkeys(&[b"laptop-1", b"phone-01"]);
+        let cookie = login(&app, &email).await;
+
+        let request = Request::get("/passkeys").header(header::COOKIE, &cookie).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page = String::from_utf8(page.to_vec()).unwrap();
+        assert!(page.contains("Key 1") && page.contains("Key 2"));
+
+        let laptop = Base64UrlSafeData::from(b"laptop-1".to_vec());
+        let body = json!({ "credential_id": laptop, "name": "Work laptop" });
+        assert_eq!(post_json(&app, "/passkeys/rename", body, Some(&cookie)).await, StatusCode::OK);
+        let names = user::get(&email).unwrap().passkeys.into_iter().map(|pk| pk.name).collect::<Vec<_>>();
+        assert_eq!(names, ["Work laptop", "Key 2"]);
+
+        // Every known passkey is excluded when adding a new one
+        let request = same_site_post(&app, "/passkeys/register", Some(&cookie)).await.body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice::<Value>(&body).unwrap();
+        assert_eq!(body["publicKey"]["excludeCredentials"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_passkeys_of_other_users_cannot_be_managed() {
+        let app = app();
+        let alice = user_with_passkeys(&[b"alice-01", b"alice-02"]);
+        let bob = login(&app, &user_with_passkeys(&[b"bob-0001", b"bob-0002"])).await;
+
+        let alice_key = Base64UrlSafeData::from(b"alice-01".to_vec());
+        let status = post_json(&app, "/passkeys/revoke", json!({ "credential_id": alice_key }), Some(&bob)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body = json!({ "credential_id": alice_key, "name": "Stolen" });
+        assert_eq!(post_json(&app, "/passkeys/rename", body, Some(&bob)).await, StatusCode::NOT_FOUND);
+
+        let passkeys = user::get(&alice).unwrap().passkeys;
+        assert_eq!(passkeys.len(), 2);
+        assert_eq!(passkeys[0].name, "Key 1");
+    }
+
+    #[tokio::test]
+    async fn test_last_passkey_cannot_be_revoked() {
+        let app = app();
+        let email = user_with_passkeys(&[b"laptop-1", b"phone-01"]);
+        let cookie = login(&app, &email).await;
+
+        let laptop = json!({ "credential_id": Base64UrlSafeData::from(b"laptop-1".to_vec()) });
+        let phone = json!({ "credential_id": Base64UrlSafeData::from(b"phone-01".to_vec()) });
+        assert_eq!(post_json(&app, "/passkeys/revoke", laptop, Some(&cookie)).await, StatusCode::OK);
+        assert_eq!(post_json(&app, "/passkeys/revoke", phone, Some(&cookie)).await, StatusCode::BAD_REQUEST);
+
+        let passkeys = user::get(&email).unwrap().passkeys;
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].passkey.cred_id().as_ref(), b"phone-01");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_registrations_of_an_email_cannot_share_the_account() {
+        let app = app();
+        let email = format!("{}@example.com", Uuid::new_v4());
+        let (first, first_options) = start_registration(&app, &email).await;
+        let (second, second_options) = start_registration(&app, &email).await;
+
+        let first_key = TestAuthenticator::new();
+        let status = finish_registration(&app, &first, &email, &first_options, &first_key).await;
+        assert_eq!(status, (StatusCode::OK, String::new()));
+
+        let status = finish_registration(&app, &second, &email, &second_options, &TestAuthenticator::new()).await;
+        assert_eq!(status, (StatusCode::BAD_REQUEST, "Invalid registration request".to_string()));
+
+        let passkeys = user::get(&email).unwrap().passkeys;
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].passkey.cred_id().as_ref(), first_key.cred_id.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_reset_mode_without_grant_is_refused() {
+        let app = app();
+        let email = verified_user();
+
+        let status = post_json(&app, "/register", json!({ "email": email, "reset_mode": true }), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_reset_complete_without_grant_is_refused() {
+        let app = app();
+        let email = verified_user();
+
+        let body = json!({
+            "email": email,
+            "first_name": "Mallory",
+            "last_name": "Doe",
+            "state_id": Uuid::new_v4(),
+            "response": {},
+            "reset_mode": true,
+        });
+        let status = post_json(&app, "/register/complete", body, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_registration_without_grant_cannot_add_a_passkey_to_an_existing_account() {
+        let app = app();
+        let email = format!("{}@example.com", Uuid::new_v4());
+        let (cookie, options) = start_registration(&app, &email).await;
+
+        // The account is created and verified while the registration is pending
+        user::create(&email, "Jane", "Doe", Uuid::new_v4()).unwrap();
+        user::verify(&email).unwrap();
+        user::add_passkey(&email, "Key 1", passkey(b"laptop-1")).unwrap();
+
+        let status = finish_registration(&app, &cookie, &email, &options, &TestAuthenticator::new()).await;
+        assert_eq!(status, (StatusCode::BAD_REQUEST, "Invalid registration request".to_string()));
+        let passkeys = user::get(&email).unwrap().passkeys;
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].passkey.cred_id().as_ref(), b"laptop-1");
+    }
+
+    #[tokio::test]
+    async fn test_recovery_link_grants_reset_for_its_email_only() {
+        let app = app();
+        let email = verified_user();
+        let other_email = verified_user();
+
+        let recovery_token = token::generate(&email, token::Purpose::Recovery).unwrap();
+        let cookie = follow_recovery_link(&app, &recovery_token).await.expect("No session cookie set");
+
+        let status = post_json(&app, "/register", json!({ "email": email, "reset_mode": true }), Some(&cookie)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let status = post_json(&app, "/register", json!({ "email": other_email, "reset_mode": true }), Some(&cookie)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_validation_token_does_not_grant_reset() {
+        let app = app();
+        let email = verified_user();
+
+        let validation_token = token::generate(&email, token::Purpose::Validation).unwrap();
+        let cookie = follow_recovery_link(&app, &validation_token).await;
+
+        let status = post_json(&app, "/register", json!({ "email": email, "reset_mode": true }), cookie.as_deref()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_recovery_link_is_single_use() {
+        let app = app();
+        let email = verified_user();
+
+        let recovery_token = token::generate(&email, token::Purpose::Recovery).unwrap();
+        assert!(follow_recovery_link(&app, &recovery_token).await.is_some());
+
+        let cookie = follow_recovery_link(&app, &recovery_token).await;
+        let status = post_json(&app, "/register", json!({ "email": email, "reset_mode": true }), cookie.as_deref()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_dev_mail_inbox_views_and_deletes() {
+        let app = app();
+        let subject = format!("Subject {}", Uuid::new_v4());
+        let pk = email::add("user@example.com", &subject, "Link: http://localhost:8080/validate/abc", None).unwrap();
+
+        let request = Request::get("/dev/mail").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains(&subject));
+        assert!(body.contains("href=\"http://localhost:8080/validate/abc\""));
+
+        let request = same_site_post(&app, &format!("/dev/mail/{}/delete", pk), None)
+            .await
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let request = Request::get(format!("/dev/mail/{}", pk)).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_author_can_edit_or_delete_a_post() {
+        let app = app();
+        let author = verified_user();
+        let other = verified_user();
+        let post_id = post::create(&author, "Original", Vec::new()).unwrap();
+
+        let other_cookie = login(&app, &other).await;
+        let body = json!({ "post_id": post_id, "text": "Hijacked" });
+        assert_eq!(post_json(&app, "/post/edit", body, Some(&other_cookie)).await, StatusCode::FORBIDDEN);
+        let body = json!({ "post_id": post_id });
+        assert_eq!(post_json(&app, "/post/delete", body, Some(&other_cookie)).await, StatusCode::FORBIDDEN);
+        assert_eq!(post::get(&post_id).unwrap().content, "Original");
+
+        let author_cookie = login(&app, &author).await;
+        let body = json!({ "post_id": post_id, "text": "Edited" });
+        assert_eq!(post_json(&app, "/post/edit", body, Some(&author_cookie)).await, StatusCode::OK);
+        assert_eq!(post::get(&post_id).unwrap().content, "Edited");
+
+        let body = json!({ "post_id": post_id });
+        assert_eq!(post_json(&app, "/post/delete", body.clone(), Some(&author_cookie)).await, StatusCode::OK);
+        assert!(post::get(&post_id).is_none());
+        assert_eq!(post_json(&app, "/post/delete", body, Some(&author_cookie)).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_post_modifications_require_a_session() {
+        let app = app();
+        let post_id = post::create(&verified_user(), "Original", Vec::new()).unwrap();
+
+        let body = json!({ "post_id": post_id, "text": "Anonymous" });
+        assert_eq!(post_json(&app, "/post/edit", body, None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_cross_site_posts_are_rejected() {
+        let app = app();
+        let post_id = post::create(&verified_user(), "Target", Vec::new()).unwrap();
+        let cookie = login(&app, &verified_user()).await;
+        let like = json!({ "post_id": post_id, "action": "like" }).to_string();
+        let status = |request: http::request::Builder| {
+            let app = app.clone();
+            let request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(like.clone()))
+                .unwrap();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        // Another origin is refused even with the session's token
+        let mut request = same_site_post(&app, "/post/like", Some(&cookie)).await;
+        request.headers_mut().unwrap().insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+        let mut request = same_site_post(&app, "/post/like", Some(&cookie)).await;
+        request.headers_mut().unwrap().remove(header::ORIGIN);
+        let request = request.header(header::REFERER, "https://evil.example/attack.html");
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+
+        // A plain cross-site form only carries the cookie, and a token must belong to the session
+        let request = Request::post("/post/like").header(header::COOKIE, &cookie);
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+        let mut request = same_site_post(&app, "/post/like", None).await;
+        request.headers_mut().unwrap().insert(header::COOKIE, cookie.parse().unwrap());
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+        assert_eq!(post::get(&post_id).unwrap().count(Reaction::Like), 0);
+
+        let request = same_site_post(&app, "/post/like", Some(&cookie)).await;
+        assert_eq!(status(request).await, StatusCode::OK);
+        assert_eq!(post::get(&post_id).unwrap().count(Reaction::Like), 1);
+
+        // Unauthenticated endpoints are protected as well
+        let request = Request::post("/recover").header(header::ORIGIN, "https://evil.example");
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_authentication_endpoints_are_rate_limited() {
+        let app = app();
+        let quotas = &config::get().rate_limit;
+        let send = |uri: &'static str, email: String, ip: [u8; 4]| {
+            let app = app.clone();
+            async move {
+                let mut request = same_site_post(&app, uri, None)
+                    .await
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(json!({ "email": email }).to_string()))
+                    .unwrap();
+                request.extensions_mut().insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((ip, 1234))));
+                app.oneshot(request).await.unwrap()
+            }
+        };
+
+        // The same email from different addresses
+        let email = format!("{}@example.com", Uuid::new_v4());
+        for i in 0..quotas.per_email_burst {
+            let response = send("/recover", email.clone(), [10, 0, 0, i as u8]).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send("/recover", email.to_uppercase(), [10, 0, 1, 0]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+        assert!(retry_after > 0 && retry_after <= quotas.per_email_refill_secs);
+
+        // Different emails from the same address, across the limited endpoints
+        for _ in 0..quotas.per_ip_burst {
+            let response = send("/login", format!("{}@example.com", Uuid::new_v4()), [10, 0, 2, 0]).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let response = send("/register", format!("{}@example.com", Uuid::new_v4()), [10, 0, 2, 0]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        let response = send("/register", format!("{}@example.com", Uuid::new_v4()), [10, 0, 2, 1]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ceremonies_can_only_be_completed_by_their_session() {
+        let app = app();
+        let email = format!("{}@example.com", Uuid::new_v4());
+        let request = same_site_post(&app, "/register", None).await;
+        let owner = request.headers_ref().unwrap()[header::COOKIE].to_str().unwrap().to_string();
+        let (status, body) = send_json(&app, request, json!({ "email": email })).await;
+        assert_eq!(status, StatusCode::OK);
+        let state_id = serde_json::from_str::<Value>(&body).unwrap()["state_id"].clone();
+
+        let complete = json!({
+            "email": email,
+            "first_name": "Jane",
+            "last_name": "Doe",
+            "state_id": state_id,
+            "response": {},
+        });
+        let request = same_site_post(&app, "/register/complete", None).await;
+        let (status, body) = send_json(&app, request, complete.clone()).await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_REQUEST, "Invalid registration session"));
+
+        // The state is still available to the browser that started the ceremony
+        let request = same_site_post(&app, "/register/complete", Some(&owner)).await;
+        let (status, body) = send_json(&app, request, complete).await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_REQUEST, "Invalid response"));
+    }
+
+    #[tokio::test]
+    async fn test_discoverable_login_resolves_the_user_from_its_handle() {
+        let app = app();
+        let email = verified_user();
+        let handle = user::user_handle(&email).unwrap();
+        assert_eq!(user::user_handle(&email).unwrap(), handle);
+        assert_eq!(user::find_by_user_handle(&handle).map(|user| user.email), Some(email));
+
+        // No email is needed and no credential is imposed on the authenticator
+        let request = same_site_post(&app, "/login/discoverable", None).await;
+        let owner = request.headers_ref().unwrap()[header::COOKIE].to_str().unwrap().to_string();
+        let (status, body) = send_json(&app, request, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        let body = serde_json::from_str::<Value>(&body).unwrap();
+        assert!(body["publicKey"]["challenge"].is_string());
+        assert!(body["publicKey"].get("allowCredentials").is_none());
+
+        let credential = json!({
+            "id": "AAAA",
+            "rawId": "AAAA",
+            "type": "public-key",
+            "extensions": {},
+            "response": {
+                "authenticatorData": "AAAA",
+                "clientDataJSON": "AAAA",
+                "signature": "AAAA",
+                "userHandle": Base64UrlSafeData::from(handle.as_bytes().to_vec()),
+            },
+        });
+        let complete = json!({ "state_id": body["state_id"], "response": credential });
+        let request = same_site_post(&app, "/login/discoverable/complete", None).await;
+        let (status, body) = send_json(&app, request, complete.clone()).await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_REQUEST, "Invalid authentication session"));
+
+        // The owner of the ceremony reaches the signature verification, which fails
+        let request = same_site_post(&app, "/login/discoverable/complete", Some(&owner)).await;
+        let (status, body) = send_json(&app, request, complete).await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_REQUEST, "Failed to complete authentication"));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_users_do_not_adopt_the_user_handle_returned_at_login() {
+        let app = app();
+        let email = format!("{}@example.com", Uuid::new_v4());
+        let authenticator = TestAuthenticator::new();
+        let (cookie, options) = start_registration(&app, &email).await;
+        assert_eq!(finish_registration(&app, &cookie, &email, &options, &authenticator).await.0, StatusCode::OK);
+        user::verify(&email).unwrap();
+
+        // Accounts created before user handles were kept have none
+        database::storage()
+            .update_user(&email, &mut |user| {
+                user.user_handle = None;
+                Ok(())
+            })
+            .unwrap();
+
+        // The handle returned by the authenticator is not covered by the signature
+        let forged = Uuid::new_v4();
+        let request = same_site_post(&app, "/login", None).await;
+        let cookie = request.headers_ref().unwrap()[header::COOKIE].to_str().unwrap().to_string();
+        let (status, body) = send_json(&app, request, json!({ "email": email })).await;
+        assert_eq!(status, StatusCode::OK);
+        let options = serde_json::from_str::<Value>(&body).unwrap();
+        let complete = json!({
+            "state_id": options["state_id"],
+            "response": authenticator.authenticate(&options, Some(forged.as_bytes())),
+        });
+        let request = same_site_post(&app, "/login/complete", Some(&cookie)).await;
+        assert_eq!(send_json(&app, request, complete).await.0, StatusCode::SEE_OTHER);
+        assert_eq!(user::get(&email).unwrap().user_handle, None);
+
+        // The passkey thus cannot be used without the email
+        let request = same_site_post(&app, "/login/discoverable", None).await;
+        let cookie = request.headers_ref().unwrap()[header::COOKIE].to_str().unwrap().to_string();
+        let (_, body) = send_json(&app, request, json!({})).await;
+        let options = serde_json::from_str::<Value>(&body).unwrap();
+        let complete = json!({
+            "state_id": options["state_id"],
+            "response": authenticator.authenticate(&options, Some(forged.as_bytes())),
+        });
+        let request = same_site_post(&app, "/login/discoverable/complete", Some(&cookie)).await;
+        let (status, body) = send_json(&app, request, complete).await;
+        assert_eq!((status, body.as_str()), (StatusCode::BAD_REQUEST, "Failed to complete authentication"));
+    }
+
+    #[tokio::test]
+    async fn test_html_forms_send_the_token_as_a_field() {
+        let app = app();
+        let pk = email::add("user@example.com", "Form", "Body", None).unwrap();
+
+        let mut request = same_site_post(&app, &format!("/dev/mail/{}/delete", pk), None).await;
+        let token = request.headers_mut().unwrap().remove(csrf::CSRF_HEADER).unwrap();
+        let request = request
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(format!("{}={}", csrf::CSRF_FORM_FIELD, token.to_str().unwrap())))
+            .unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::SEE_OTHER);
+        assert!(email::get(pk).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reactions_are_tracked_per_user() {
+        let app = app();
+        let author = verified_user();
+        let post_id = post::create(&author, "Hello", Vec::new()).unwrap();
+        let alice = login(&app, &verified_user()).await;
+        let bob = login(&app, &verified_user()).await;
+
+        let like = json!({ "post_id": post_id, "action": "like" });
+        let dislike = json!({ "post_id": post_id, "action": "dislike" });
+        assert_eq!(post_json(&app, "/post/like", like.clone(), Some(&alice)).await, StatusCode::OK);
+        assert_eq!(post_json(&app, "/post/like", like.clone(), Some(&bob)).await, StatusCode::OK);
+        let post = post::get(&post_id).unwrap();
+        assert_eq!((post.count(Reaction::Like), post.count(Reaction::Dislike)), (2, 0));
+
+        // Bob switches to a dislike, then withdraws it, without touching Alice's like
+        assert_eq!(post_json(&app, "/post/like", dislike.clone(), Some(&bob)).await, StatusCode::OK);
+        let post = post::get(&post_id).unwrap();
+        assert_eq!((post.count(Reaction::Like), post.count(Reaction::Dislike)), (1, 1));
+        assert_eq!(post_json(&app, "/post/like", dislike, Some(&bob)).await, StatusCode::OK);
+        let post = post::get(&post_id).unwrap();
+        assert_eq!((post.count(Reaction::Like), post.count(Reaction::Dislike)), (1, 0));
+
+        let request = Request::get("/home").header(header::COOKIE, &alice).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(&format!(r#"id="like-{}" class="btn btn-success""#, post_id)));
+    }
+
+    /// Poste un commentaire et retourne son identifiant
+    async fn comment(app: &Router, cookie: &str, body: Value) -> Result<Uuid, StatusCode> {
+        let request = same_site_post(app, "/post/comment", Some(cookie))
+            .await
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        if response.status() != StatusCode::OK {
+            return Err(response.status());
+        }
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        Ok(serde_json::from_value(body["comment_id"].clone()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_comments_allow_a_single_level_of_replies() {
+        let app = app();
+        let post_id = post::create(&verified_user(), "Discuss", Vec::new()).unwrap();
+        let alice = login(&app, &verified_user()).await;
+        let bob = login(&app, &verified_user()).await;
+
+        let top = comment(&app, &alice, json!({ "post_id": post_id, "text": "First" })).await.unwrap();
+        let reply = comment(&app, &bob, json!({ "post_id": post_id, "text": "Reply", "parent_id": top }))
+            .await
+            .unwrap();
+        let nested = comment(&app, &alice, json!({ "post_id": post_id, "text": "Nested", "parent_id": reply })).await;
+        assert_eq!(nested, Err(StatusCode::BAD_REQUEST));
+        let invalid = comment(&app, &alice, json!({ "post_id": post_id, "text": "<script>" })).await;
+        assert_eq!(invalid, Err(StatusCode::BAD_REQUEST));
+        assert_eq!(post::get(&post_id).unwrap().comments.len(), 2);
+
+        // Only the author can delete a comment, which removes its replies too
+        let body = json!({ "post_id": post_id, "comment_id": top });
+        assert_eq!(post_json(&app, "/post/comment/delete", body.clone(), Some(&bob)).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_json(&app, "/post/comment/delete", body, Some(&alice)).await, StatusCode::OK);
+        assert!(post::get(&post_id).unwrap().comments.is_empty());
+    }
+
+    /// Récupère une page du fil en JSON
+    async fn get_feed(app: &Router, cookie: &str, query: &str) -> (StatusCode, Value) {
+        let request = Request::get(format!("/feed?{}", query)).header(header::COOKIE, cookie).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_feed_is_paginated_sorted_and_filtered() {
+        let app = app();
+        let author = verified_user();
+        let cookie = login(&app, &verified_user()).await;
+        let ids: Vec<Uuid> = (0..5).map(|i| post::create(&author, &format!("Post {}", i), Vec::new()).unwrap()).collect();
+        post::react(&ids[2], "fan@example.com", Reaction::Like).unwrap();
+        let handle = user::get(&author).unwrap().user_handle.unwrap();
+
+        // Walking the pages returns every post of the author exactly once
+        let mut seen = Vec::new();
+        let mut query = format!("author={}&limit=2", handle);
+        loop {
+            let (status, page) = get_feed(&app, &cookie, &query).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(page["posts"].as_array().unwrap().len() <= 2);
+            seen.extend(page["posts"].as_array().unwrap().iter().map(|post| post["id"].as_str().unwrap().to_string()));
+            match page["next_cursor"].as_str() {
+                Some(cursor) => query = format!("author={}&limit=2&cursor={}", handle, cursor),
+                None => break,
+            }
+        }
+        let mut expected: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+        seen.sort();
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        let (_, page) = get_feed(&app, &cookie, &format!("author={}&sort=likes&limit=1", handle)).await;
+        assert_eq!(page["posts"][0]["id"], ids[2].to_string());
+        assert_eq!(page["posts"][0]["likes"], 1);
+
+        // Authors are identified by their handle, their email is never exposed
+        assert_eq!(page["posts"][0]["author"], handle.to_string());
+        assert!(!page.to_string().contains(&author));
+        let response = get_with_headers(&app, &format!("/home?author={}", handle), Some(&cookie), &[]).await;
+        let home = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let home = String::from_utf8(home.to_vec()).unwrap();
+        assert!(home.contains(&handle.to_string()) && home.contains("Posts by Jane Doe"));
+        assert!(!home.contains(&author));
+        assert_eq!(get_feed(&app, &cookie, &format!("author={}", author)).await.0, StatusCode::BAD_REQUEST);
+
+        assert_eq!(get_feed(&app, &cookie, "cursor=garbage").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(get_feed(&app, &cookie, "sort=oldest").await.0, StatusCode::BAD_REQUEST);
+    }
+
+    /// Crée un post avec une image via le formulaire multipart
+    async fn create_post_with_image(app: &Router, cookie: &str, filename: &str, image: &[u8]) -> Response<Body> {
+        let boundary = "lab02-boundary";
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"text\"\r\n\r\nWith an image\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            b = boundary,
+            f = filename
+        )
+        .into_bytes();
+        body.extend_from_slice(image);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let request = same_site_post(app, "/post/create", Some(cookie))
+            .await
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_images_are_stored_in_several_sizes() {
+        let app = app();
+        let author = verified_user();
+        let cookie = login(&app, &author).await;
+
+        let mut image = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(1500, 300))
+            .write_to(&mut image, image::ImageFormat::Png)
+            .unwrap();
+        let response = create_post_with_image(&app, &cookie, "wide.png", image.get_ref()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let post = post::get(&serde_json::from_value(body["post_id"].clone()).unwrap()).unwrap();
+
+        let sizes: Vec<(u32, u32)> = post.image_variants.iter().map(|v| (v.width, v.height)).collect();
+        assert_eq!(sizes, vec![(320, 64), (1024, 205), (1500, 300)]);
+        assert_eq!(post.image_path.as_ref(), post.image_variants.last().map(|v| &v.path));
+        for variant in &post.image_variants {
+            assert!(variant.path.ends_with(".png"));
+            let file = config::get().data_path(consts::UPLOADS_DIR).join(variant.path.trim_start_matches("/uploads/"));
+            assert!(file.exists());
+        }
+
+        // An identical upload reuses the same files, which are kept until no post uses them
+        let response = create_post_with_image(&app, &cookie, "wide.png", image.get_ref()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let copy = post::get(&serde_json::from_value(body["post_id"].clone()).unwrap()).unwrap();
+        assert_eq!(copy.image_variants, post.image_variants);
+
+        let files: Vec<_> = post
+            .image_variants
+            .iter()
+            .map(|v| config::get().data_path(consts::UPLOADS_DIR).join(v.path.trim_start_matches("/uploads/")))
+            .collect();
+        assert_eq!(post_json(&app, "/post/delete", json!({ "post_id": post.id }), Some(&cookie)).await, StatusCode::OK);
+        assert!(files.iter().all(|file| file.exists()));
+        assert_eq!(post_json(&app, "/post/delete", json!({ "post_id": copy.id }), Some(&cookie)).await, StatusCode::OK);
+        assert!(files.iter().all(|file| !file.exists()));
+    }
+
+    /// Requête GET authentifiée avec des en-têtes supplémentaires
+    async fn get_with_headers(
+        app: &Router,
+        uri: &str,
+        cookie: Option<&str>,
+        headers: &[(header::HeaderName, &str)],
+    ) -> Response<Body> {
+        let mut request = Request::get(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_served_with_access_control_and_cache_headers() {
+        let app = app();
+        let cookie = login(&app, &verified_user()).await;
+        let mut image = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(40, 30, image::Rgb([1, 2, 3])))
+            .write_to(&mut image, image::ImageFormat::Png)
+            .unwrap();
+        let response = create_post_with_image(&app, &cookie, "small.png", image.get_ref()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let post = post::get(&serde_json::from_value(body["post_id"].clone()).unwrap()).unwrap();
+        let path = post.image_path.unwrap();
+
+        let response = get_with_headers(&app, &path, Some(&cookie), &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert!(response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().starts_with("inline"));
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        let full = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let response = get_with_headers(&app, &path, Some(&cookie), &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = get_with_headers(&app, &path, Some(&cookie), &[(header::RANGE, "bytes=0-9")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], format!("bytes 0-9/{}", full.len()));
+        let part = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(part, full[..10]);
+        let response = get_with_headers(&app, &path, Some(&cookie), &[(header::RANGE, "bytes=100000-")]).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        // Anonymous users, unreferenced files and other files of the data directory are refused
+        assert_eq!(get_with_headers(&app, &path, None, &[]).await.status(), StatusCode::UNAUTHORIZED);
+        let orphan = crate::database::upload::store(Uuid::new_v4().as_bytes(), "png").unwrap();
+        assert_eq!(get_with_headers(&app, &orphan, Some(&cookie), &[]).await.status(), StatusCode::NOT_FOUND);
+        let traversal = get_with_headers(&app, "/uploads/..%2Flab02.sqlite3", Some(&cookie), &[]).await;
+        assert_eq!(traversal.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_post_markup_is_rendered_safely() {
+        let app = app();
+        let author = verified_user();
+        let cookie = login(&app, &author).await;
+        let source = "**Hello** [site](https://example.com)\n`<b>`";
+        let post_id = post::create(&author, source, Vec::new()).unwrap();
+
+        let handle = user::get(&author).unwrap().user_handle.unwrap();
+        let (_, page) = get_feed(&app, &cookie, &format!("author={}", handle)).await;
+        assert_eq!(page["posts"][0]["id"], post_id.to_string());
+        assert_eq!(page["posts"][0]["content"], source);
+        assert_eq!(
+            page["posts"][0]["content_html"],
+            "<strong>Hello</strong> <a href=\"https://example.com\" rel=\"nofollow noopener\">site</a><br><code>&lt;b&gt;</code>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pages_are_served_with_a_strict_content_security_policy() {
+        let app = app();
+        let cookie = login(&app, &verified_user()).await;
+
+        let mut nonces = Vec::new();
+        for page in ["/", "/login", "/register", "/recover", "/home", "/passkeys"] {
+            let response = get_with_headers(&app, page, Some(&cookie), &[]).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", page);
+            let headers = response.headers().clone();
+            assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+            assert_eq!(headers[header::REFERRER_POLICY], "same-origin");
+            assert!(headers.contains_key("permissions-policy"));
+            // The test configuration is served over plain HTTP
+            assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+            let policy = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+            assert!(!policy.contains("unsafe-inline"));
+            let nonce = policy.split("'nonce-").nth(1).unwrap().split('\'').next().unwrap().to_string();
+
+            // Every script carries the nonce of its response, and no inline handler is left
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.matches("<script").count() > 0, "{}", page);
+            assert_eq!(
+                body.matches("<script").count(),
+                body.matches(&format!("<script nonce=\"{}\" src=", nonce)).count(),
+                "{}",
+                page
+            );
+            assert!(!body.contains(" onclick=") && !body.contains(" style="), "{}", page);
+            nonces.push(nonce);
+        }
+        nonces.sort();
+        nonces.dedup();
+        assert_eq!(nonces.len(), 6);
+
+        let response = get_with_headers(&app, "/static/js/home.js", None, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+    }
+}
@@ -0,0 +1,57 @@
+//! En-têtes de sécurité ajoutés à toutes les réponses.
+//! La Content-Security-Policy n'autorise que les scripts portant le nonce de la requête,
+//! transmis aux templates Handlebars.
+
+use crate::{config, consts};
+use axum::{
+    extract::Request,
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+/// Nonce des scripts autorisés pour la requête en cours, à ajouter à chaque balise `<script>`
+#[derive(Clone)]
+pub struct CspNonce(pub String);
+
+/// Fonctionnalités du navigateur inutilisées par l'application, seul WebAuthn est conservé
+const PERMISSIONS_POLICY: &str = "camera=(), microphone=(), geolocation=(), payment=(), usb=(), \
+    publickey-credentials-create=(self), publickey-credentials-get=(self)";
+
+/// Content-Security-Policy d'une réponse. Les styles ne sont chargés que depuis l'application
+/// et le CDN de Bootstrap, les attributs `style` et les handlers inline sont refusés.
+fn content_security_policy(nonce: &str) -> String {
+    format!(
+        "default-src 'self'; script-src 'nonce-{}' 'strict-dynamic'; style-src 'self' https://cdn.jsdelivr.net; \
+         img-src 'self' data:; connect-src 'self'; object-src 'none'; base-uri 'none'; form-action 'self'; \
+         frame-ancestors 'none'",
+        nonce
+    )
+}
+
+/// Middleware générant le nonce de la requête et ajoutant les en-têtes de sécurité à la réponse
+pub async fn add_security_headers(mut request: Request, next: Next) -> Response {
+    let nonce = Uuid::new_v4().simple().to_string();
+    request.extensions_mut().insert(CspNonce(nonce.clone()));
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    if let Ok(policy) = HeaderValue::from_str(&content_security_policy(&nonce)) {
+        headers.insert(header::CONTENT_SECURITY_POLICY, policy);
+    }
+    headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("same-origin"));
+    headers.insert(HeaderName::from_static("permissions-policy"), HeaderValue::from_static(PERMISSIONS_POLICY));
+
+    // HSTS n'a de sens que si l'application est servie en HTTPS
+    if config::get().public_base_url.scheme() == "https" {
+        let hsts = format!("max-age={}; includeSubDomains", consts::HSTS_MAX_AGE_SECS);
+        if let Ok(hsts) = HeaderValue::from_str(&hsts) {
+            headers.insert(header::STRICT_TRANSPORT_SECURITY, hsts);
+        }
+    }
+
+    response
+}
@@ -0,0 +1,62 @@
+//! Sélection du store de sessions utilisé par le routeur.
+//! Le store est choisi par la configuration (`session.store`, `persistent` par défaut, ou `memory`).
+
+use crate::config::{SessionConfig, SessionStoreKind};
+use crate::database::session::PersistentStore;
+use async_trait::async_trait;
+use std::io;
+use tower_sessions::{session::Id, ExpiredDeletion, MemoryStore, Session, SessionStore};
+
+/// Store de sessions sélectionné au démarrage
+#[derive(Clone, Debug)]
+pub enum SessionBackend {
+    Memory(MemoryStore),         // Sessions perdues au redémarrage
+    Persistent(PersistentStore), // Sessions enregistrées dans le stockage configuré
+}
+
+impl SessionBackend {
+    /// Construit le store choisi par la configuration
+    pub fn from_config(config: &SessionConfig) -> Self {
+        match config.store {
+            SessionStoreKind::Memory => Self::Memory(MemoryStore::default()),
+            SessionStoreKind::Persistent => Self::Persistent(PersistentStore),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for SessionBackend {
+    type Error = io::Error;
+
+    async fn save(&self, session: &Session) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(store) => store.save(session).await.map_err(io::Error::other),
+            Self::Persistent(store) => store.save(session).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> Result<Option<Session>, Self::Error> {
+        match self {
+            Self::Memory(store) => store.load(session_id).await.map_err(io::Error::other),
+            Self::Persistent(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(store) => store.delete(session_id).await.map_err(io::Error::other),
+            Self::Persistent(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SessionBackend {
+    async fn delete_expired(&self) -> Result<(), Self::Error> {
+        match self {
+            // Expired sessions are filtered out on load by the memory store
+            Self::Memory(_) => Ok(()),
+            Self::Persistent(store) => store.delete_expired().await,
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! Configuration de l'application.
+//! Chargée au démarrage depuis un fichier YAML (`config.yaml`, ou le chemin donné par `LAB02_CONFIG`),
+//! surchargée par les variables d'environnement `LAB02_*`, puis validée avant le démarrage du serveur.
+
+use anyhow::{anyhow, bail, Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use url::Url;
+use crate::utils::input::ImageLimits;
+
+/// Variable d'environnement donnant le chemin du fichier de configuration
+const CONFIG_PATH_VAR: &str = "LAB02_CONFIG";
+/// Fichier de configuration utilisé si `LAB02_CONFIG` n'est pas défini, il est optionnel
+const DEFAULT_CONFIG_PATH: &str = "config.yaml";
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Configuration complète de l'application
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: SocketAddr, // Adresse d'écoute du serveur HTTP
+    pub public_base_url: Url,     // URL publique, utilisée dans les liens envoyés par email
+    pub data_dir: PathBuf,        // Dossier contenant les bases de données et les fichiers uploadés
+    pub storage: StorageConfig,
+    pub webauthn: WebauthnConfig,
+    pub uploads: UploadsConfig,
+    pub session: SessionConfig,
+    pub rate_limit: RateLimitConfig,
+}
+
+/// Backends de stockage disponibles
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    Sqlite, // Base SQLite dans le dossier de données
+    Yaml,   // Fichiers YAML dans le dossier de données
+}
+
+/// Configuration du stockage des données
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+}
+
+/// Configuration de la Relying Party WebAuthn
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebauthnConfig {
+    pub rp_id: Option<String>,   // Par défaut, l'hôte de `public_base_url`
+    pub rp_name: Option<String>, // Par défaut, le RP ID
+    pub origins: Vec<Url>,       // Par défaut, l'origine de `public_base_url`
+}
+
+/// Limites appliquées aux uploads
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadsConfig {
+    pub max_request_bytes: usize,     // Taille maximale du corps d'une requête de création de post
+    pub max_file_bytes: usize,        // Taille maximale d'une image uploadée
+    pub max_image_dimension: u32,     // Largeur et hauteur maximales d'une image, en pixels
+    pub max_image_pixels: u64,        // Nombre maximal de pixels d'une image, lu dans son en-tête
+    pub max_decoder_alloc_bytes: u64, // Mémoire maximale allouée par le décodeur d'images
+}
+
+/// Stores de sessions disponibles
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStoreKind {
+    Memory,
+    #[serde(alias = "file")]
+    Persistent, // Sessions enregistrées dans le stockage, `file` étant l'ancien nom
+}
+
+/// Configuration des sessions
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub store: SessionStoreKind,
+    pub inactivity_timeout_secs: i64, // Durée d'inactivité avant l'expiration d'une session
+    pub sweep_interval_secs: u64,     // Intervalle de suppression des sessions expirées
+}
+
+/// Quotas des routes d'authentification et de récupération, sous forme de seaux de jetons
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_ip_burst: u32,                 // Requêtes autorisées d'affilée depuis une même adresse IP
+    pub per_ip_refill_secs: u64,           // Délai de recharge d'une requête pour une adresse IP
+    pub per_email_burst: u32,              // Requêtes autorisées d'affilée pour un même email
+    pub per_email_refill_secs: u64,        // Délai de recharge d'une requête pour un email
+    pub trust_forwarded_for: bool,         // Adresse IP lue dans `X-Forwarded-For`, derrière un reverse proxy uniquement
+    pub failed_login_alert_threshold: u32, // Échecs d'authentification consécutifs d'un compte déclenchant une alerte
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            public_base_url: Url::parse("http://localhost:8080").unwrap(),
+            data_dir: PathBuf::from("./data"),
+            storage: StorageConfig::default(),
+            webauthn: WebauthnConfig::default(),
+            uploads: UploadsConfig::default(),
+            session: SessionConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Sqlite,
+        }
+    }
+}
+
+impl UploadsConfig {
+    /// Limites appliquées à chaque image uploadée
+    pub fn image_limits(&self) -> ImageLimits {
+        ImageLimits {
+            max_file_bytes: self.max_file_bytes,
+            max_dimension: self.max_image_dimension,
+            max_pixels: self.max_image_pixels,
+            max_decoder_alloc_bytes: self.max_decoder_alloc_bytes,
+        }
+    }
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        let limits = ImageLimits::default();
+        Self {
+            max_request_bytes: 10 * 1024 * 1024,
+            max_file_bytes: limits.max_file_bytes,
+            max_image_dimension: limits.max_dimension,
+            max_image_pixels: limits.max_pixels,
+            max_decoder_alloc_bytes: limits.max_decoder_alloc_bytes,
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            store: SessionStoreKind::Persistent,
+            inactivity_timeout_secs: 60 * 60 * 24,
+            sweep_interval_secs: 60 * 10,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_ip_burst: 20,
+            per_ip_refill_secs: 6,
+            per_email_burst: 5,
+            per_email_refill_secs: 60,
+            trust_forwarded_for: false,
+            failed_login_alert_threshold: 5,
+        }
+    }
+}
+
+impl FromStr for SessionStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "persistent" | "file" => Ok(Self::Persistent),
+            other => Err(anyhow!("Unknown session store '{}'", other)),
+        }
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sqlite" => Ok(Self::Sqlite),
+            "yaml" => Ok(Self::Yaml),
+            other => Err(anyhow!("Unknown storage backend '{}'", other)),
+        }
+    }
+}
+
+impl Config {
+    /// Charge la configuration depuis le fichier et l'environnement, puis la valide
+    pub fn load() -> Result<Self> {
+        let mut config = match env::var(CONFIG_PATH_VAR) {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            Err(_) => Self::default(),
+        };
+
+        config.apply_env(|name| env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse configuration file {}", path.display()))
+    }
+
+    /// Surcharge les valeurs par celles des variables d'environnement `LAB02_*` définies
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        fn parse<T: FromStr>(name: &str, value: String) -> Result<T> {
+            value.parse().map_err(|_| anyhow!("Invalid value for {}", name))
+        }
+
+        if let Some(value) = var("LAB02_BIND_ADDRESS") {
+            self.bind_address = parse("LAB02_BIND_ADDRESS", value)?;
+        }
+        if let Some(value) = var("LAB02_PUBLIC_BASE_URL") {
+            self.public_base_url = parse("LAB02_PUBLIC_BASE_URL", value)?;
+        }
+        if let Some(value) = var("LAB02_DATA_DIR") {
+            self.data_dir = PathBuf::from(value);
+        }
+        if let Some(value) = var("LAB02_STORAGE_BACKEND") {
+            self.storage.backend = value.parse()?;
+        }
+        if let Some(value) = var("LAB02_RP_ID") {
+            self.webauthn.rp_id = Some(value);
+        }
+        if let Some(value) = var("LAB02_RP_ORIGINS") {
+            self.webauthn.origins = value
+                .split(',')
+                .map(|origin| parse("LAB02_RP_ORIGINS", origin.trim().to_string()))
+                .collect::<Result<_>>()?;
+        }
+        if let Some(value) = var("LAB02_MAX_REQUEST_BYTES") {
+            self.uploads.max_request_bytes = parse("LAB02_MAX_REQUEST_BYTES", value)?;
+        }
+        if let Some(value) = var("LAB02_MAX_FILE_BYTES") {
+            self.uploads.max_file_bytes = parse("LAB02_MAX_FILE_BYTES", value)?;
+        }
+        if let Some(value) = var("LAB02_MAX_IMAGE_DIMENSION") {
+            self.uploads.max_image_dimension = parse("LAB02_MAX_IMAGE_DIMENSION", value)?;
+        }
+        if let Some(value) = var("LAB02_MAX_IMAGE_PIXELS") {
+            self.uploads.max_image_pixels = parse("LAB02_MAX_IMAGE_PIXELS", value)?;
+        }
+        if let Some(value) = var("LAB02_MAX_DECODER_ALLOC_BYTES") {
+            self.uploads.max_decoder_alloc_bytes = parse("LAB02_MAX_DECODER_ALLOC_BYTES", value)?;
+        }
+        if let Some(value) = var("LAB02_SESSION_STORE") {
+            self.session.store = value.parse()?;
+        }
+        if let Some(value) = var("LAB02_SESSION_INACTIVITY_TIMEOUT_SECS") {
+            self.session.inactivity_timeout_secs = parse("LAB02_SESSION_INACTIVITY_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("LAB02_RATE_LIMIT_PER_IP_BURST") {
+            self.rate_limit.per_ip_burst = parse("LAB02_RATE_LIMIT_PER_IP_BURST", value)?;
+        }
+        if let Some(value) = var("LAB02_RATE_LIMIT_PER_EMAIL_BURST") {
+            self.rate_limit.per_email_burst = parse("LAB02_RATE_LIMIT_PER_EMAIL_BURST", value)?;
+        }
+        if let Some(value) = var("LAB02_TRUST_FORWARDED_FOR") {
+            self.rate_limit.trust_forwarded_for = parse("LAB02_TRUST_FORWARDED_FOR", value)?;
+        }
+        Ok(())
+    }
+
+    /// Vérifie la cohérence de la configuration
+    fn validate(&self) -> Result<()> {
+        if !matches!(self.public_base_url.scheme(), "http" | "https") || self.public_base_url.host_str().is_none() {
+            bail!("public_base_url must be an absolute http(s) URL");
+        }
+        if self.public_base_url.query().is_some() || self.public_base_url.fragment().is_some() {
+            bail!("public_base_url must not contain a query or a fragment");
+        }
+
+        let rp_id = self.rp_id();
+        if rp_id.is_empty() {
+            bail!("webauthn.rp_id must not be empty");
+        }
+        for origin in self.origins() {
+            let host = origin
+                .host_str()
+                .filter(|_| matches!(origin.scheme(), "http" | "https"))
+                .ok_or_else(|| anyhow!("Invalid WebAuthn origin {}", origin))?;
+            if host != rp_id && !host.ends_with(&format!(".{}", rp_id)) {
+                bail!("WebAuthn origin {} is not within the RP ID {}", origin, rp_id);
+            }
+        }
+
+        let uploads = &self.uploads;
+        if uploads.max_request_bytes == 0
+            || uploads.max_file_bytes == 0
+            || uploads.max_image_dimension == 0
+            || uploads.max_image_pixels == 0
+            || uploads.max_decoder_alloc_bytes == 0
+        {
+            bail!("uploads limits must be positive");
+        }
+        if uploads.max_file_bytes > uploads.max_request_bytes {
+            bail!("uploads.max_file_bytes must not exceed uploads.max_request_bytes");
+        }
+        if self.session.inactivity_timeout_secs <= 0 || self.session.sweep_interval_secs == 0 {
+            bail!("session durations must be positive");
+        }
+        let rate_limit = &self.rate_limit;
+        if rate_limit.per_ip_burst == 0
+            || rate_limit.per_ip_refill_secs == 0
+            || rate_limit.per_email_burst == 0
+            || rate_limit.per_email_refill_secs == 0
+            || rate_limit.failed_login_alert_threshold == 0
+        {
+            bail!("rate_limit quotas must be positive");
+        }
+        if self.data_dir.exists() && !self.data_dir.is_dir() {
+            bail!("data_dir {} is not a directory", self.data_dir.display());
+        }
+        Ok(())
+    }
+
+    /// RP ID WebAuthn effectif
+    pub fn rp_id(&self) -> &str {
+        self.webauthn
+            .rp_id
+            .as_deref()
+            .or(self.public_base_url.host_str())
+            .unwrap_or_default()
+    }
+
+    /// Origines WebAuthn autorisées
+    pub fn origins(&self) -> Vec<Url> {
+        if self.webauthn.origins.is_empty() {
+            vec![self.public_base_url.clone()]
+        } else {
+            self.webauthn.origins.clone()
+        }
+    }
+
+    /// Construit une URL publique à partir d'un chemin absolu (e.g. `/validate/<token>`)
+    pub fn public_url(&self, path: &str) -> String {
+        format!("{}{}", self.public_base_url.as_str().trim_end_matches('/'), path)
+    }
+
+    /// Chemin d'un fichier du dossier de données
+    pub fn data_path(&self, name: &str) -> PathBuf {
+        self.data_dir.join(name)
+    }
+
+    /// Configuration utilisée par les tests, qui ne touche pas aux données réelles
+    #[cfg(test)]
+    fn for_tests() -> Self {
+        Self {
+            data_dir: PathBuf::from("./target/test-data"),
+            session: SessionConfig {
+                store: SessionStoreKind::Memory,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Définit la configuration globale, ne peut être appelé qu'une fois
+pub fn init(config: Config) -> Result<()> {
+    CONFIG.set(config).map_err(|_| anyhow!("Configuration already initialized"))
+}
+
+/// Retourne la configuration globale
+pub fn get() -> &'static Config {
+    #[cfg(test)]
+    return CONFIG.get_or_init(Config::for_tests);
+
+    #[cfg(not(test))]
+    CONFIG.get().expect("Configuration not initialized")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<Config> {
+        let config: Config = serde_yaml::from_str(yaml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.rp_id(), "localhost");
+        assert_eq!(config.origins(), vec![Url::parse("http://localhost:8080").unwrap()]);
+        assert_eq!(config.public_url("/validate/abc"), "http://localhost:8080/validate/abc");
+    }
+
+    #[test]
+    fn test_reverse_proxy_configuration() {
+        let config = parse(
+            "public_base_url: https://lab02.example.com/\n\
+             webauthn:\n  rp_id: example.com\n  origins: [https://lab02.example.com, https://www.example.com]\n",
+        )
+        .unwrap();
+        assert_eq!(config.rp_id(), "example.com");
+        assert_eq!(config.origins().len(), 2);
+        assert_eq!(config.public_url("/recover/abc"), "https://lab02.example.com/recover/abc");
+    }
+
+    #[test]
+    fn test_origin_outside_rp_id_is_rejected() {
+        let yaml = "webauthn:\n  rp_id: example.com\n  origins: [https://evil.com]\n";
+        assert!(parse(yaml).is_err());
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        assert!(parse("http_port: 8080\n").is_err());
+    }
+
+    #[test]
+    fn test_invalid_limits_are_rejected() {
+        assert!(parse("uploads:\n  max_request_bytes: 0\n").is_err());
+        assert!(parse("uploads:\n  max_image_pixels: 0\n").is_err());
+        assert!(parse("uploads:\n  max_request_bytes: 1000\n  max_file_bytes: 2000\n").is_err());
+        assert!(parse("session:\n  inactivity_timeout_secs: 0\n").is_err());
+        assert!(parse("rate_limit:\n  per_email_burst: 0\n").is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let mut config = Config::default();
+        config
+            .apply_env(|name| match name {
+                "LAB02_PUBLIC_BASE_URL" => Some("https://lab02.example.com".to_string()),
+                "LAB02_SESSION_STORE" => Some("memory".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.rp_id(), "lab02.example.com");
+        assert_eq!(config.session.store, SessionStoreKind::Memory);
+
+        let result = config.apply_env(|name| (name == "LAB02_SESSION_STORE").then(|| "redis".to_string()));
+        assert!(result.is_err());
+
+        // The former name of the persistent store is still accepted
+        config.apply_env(|name| (name == "LAB02_SESSION_STORE").then(|| "file".to_string())).unwrap();
+        assert_eq!(config.session.store, SessionStoreKind::Persistent);
+    }
+}
@@ -1,8 +1,24 @@
 //! Définition des constantes globales pour l'application.
+//! Les valeurs dépendant du déploiement sont définies dans la configuration (voir `config.rs`).
 
-pub const HTTP_PORT: u16 = 8080; // Port par défaut pour le serveur HTTP.
-pub const USERS_DB_PATH: &str = "./data/users.yaml"; // Chemin de la base de données des utilisateurs.
-pub const EMAILS_DB_PATH: &str = "./data/emails.yaml"; // Chemin de la base de données des emails.
-pub const POSTS_DB_PATH: &str = "./data/posts.yaml"; // Chemin de la base de données des posts.
-pub const UPLOADS_DIR: &str = "./data/uploads"; // Dossier pour les fichiers uploadés.
-
+pub const SQLITE_DB_FILE: &str = "lab02.sqlite3"; // Fichier de la base de données SQLite.
+pub const USERS_DB_FILE: &str = "users.yaml"; // Fichier de la base de données des utilisateurs.
+pub const EMAILS_DB_FILE: &str = "emails.yaml"; // Fichier de la base de données des emails.
+pub const TOKENS_DB_FILE: &str = "tokens.yaml"; // Fichier de la base de données des tokens.
+pub const SESSIONS_DIR: &str = "sessions"; // Dossier des sessions avec le stockage YAML, un fichier par session.
+pub const POSTS_DB_FILE: &str = "posts.yaml"; // Fichier de la base de données des posts.
+pub const YAML_BACKUP_COUNT: usize = 3; // Nombre de sauvegardes conservées pour chaque fichier YAML.
+pub const UPLOADS_DIR: &str = "uploads"; // Dossier pour les fichiers uploadés, relatif au dossier de données.
+pub const UPLOAD_GC_GRACE_SECS: u64 = 60 * 60; // Âge minimal d'un fichier non référencé avant sa suppression par `gc-uploads`.
+pub const IMAGE_VARIANTS: [(&str, u32); 3] = [("thumb", 320), ("medium", 1024), ("full", 2048)]; // Tailles générées pour chaque image, selon la plus grande dimension.
+pub const VALIDATION_TOKEN_TTL_SECS: u64 = 60 * 60 * 48; // Durée de validité d'un lien de validation de compte.
+pub const RECOVERY_TOKEN_TTL_SECS: u64 = 60 * 30; // Durée de validité d'un lien de récupération de compte.
+pub const RESET_GRANT_TTL_SECS: u64 = 60 * 10; // Durée pendant laquelle une récupération de compte peut être finalisée.
+pub const WEBAUTHN_TIMEOUT_SECS: u64 = 60 * 5; // Durée laissée à l'utilisateur pour une cérémonie WebAuthn, au-delà son état est oublié.
+pub const CEREMONY_STORE_CAPACITY: usize = 10_000; // Nombre maximal de cérémonies WebAuthn en cours de chaque type.
+pub const CEREMONY_SWEEP_INTERVAL_SECS: u64 = 60; // Intervalle de suppression des cérémonies WebAuthn expirées.
+pub const TOKEN_SWEEP_INTERVAL_SECS: u64 = 60 * 10; // Intervalle de suppression des tokens expirés.
+pub const FEED_PAGE_SIZE: usize = 20; // Nombre de posts par page du fil.
+pub const FEED_MAX_PAGE_SIZE: usize = 100; // Nombre maximal de posts pouvant être demandés par page.
+pub const STATIC_DIR: &str = "static"; // Dossier des scripts et feuilles de style servis sous `/static`.
+pub const HSTS_MAX_AGE_SECS: u64 = 60 * 60 * 24 * 365; // Durée pendant laquelle le navigateur impose HTTPS.
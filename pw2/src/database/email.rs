@@ -0,0 +1,42 @@
+//! Gestion des emails
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use super::storage;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Email {
+    pub pk: u64,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+    #[serde(default)]
+    pub html_body: Option<String>,
+}
+
+/// Ajoute un email, retourne son identifiant
+pub fn add(to: &str, subject: &str, body: &str, html_body: Option<&str>) -> Result<u64> {
+    storage().insert_email(&Email {
+        pk: 0,
+        to: to.to_string(),
+        subject: subject.to_string(),
+        body: body.to_string(),
+        html_body: html_body.map(str::to_string),
+    })
+}
+
+/// Retourne tous les emails, du plus récent au plus ancien
+pub fn all() -> Result<Vec<Email>> {
+    let mut emails = storage().emails()?;
+    emails.sort_by_key(|email| std::cmp::Reverse(email.pk));
+    Ok(emails)
+}
+
+pub fn get(pk: u64) -> Option<Email> {
+    storage().get_email(pk).ok()?
+}
+
+/// Supprime un email, retourne `false` s'il n'existait pas
+pub fn delete(pk: u64) -> Result<bool> {
+    storage().delete_email(pk)
+}
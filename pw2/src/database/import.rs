@@ -0,0 +1,122 @@
+//! Import des anciens fichiers YAML dans le stockage configuré.
+//! Lancé une seule fois, via `lab02 import-yaml [dossier]`, sur un stockage vide.
+
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use super::{storage::Storage, yaml::YamlStorage};
+
+/// Nombre d'éléments importés
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub users: usize,
+    pub tokens: usize,
+    pub emails: usize,
+    pub posts: usize,
+}
+
+/// Importe les fichiers YAML du dossier `source` dans `target`, qui doit être vide.
+/// Les emails sont renumérotés dans leur ordre d'origine.
+pub fn import_yaml(source: &Path, target: &dyn Storage) -> Result<ImportReport> {
+    let is_empty = target.users()?.is_empty()
+        && target.tokens()?.is_empty()
+        && target.emails()?.is_empty()
+        && target.posts()?.is_empty();
+    if !is_empty {
+        bail!("The target storage is not empty, refusing to import");
+    }
+
+    let source = YamlStorage::open(source).context("Failed to load the YAML files")?;
+    let mut report = ImportReport::default();
+
+    for user in source.users()? {
+        if !target.insert_user(&user)? {
+            bail!("Duplicate user {}", user.email);
+        }
+        report.users += 1;
+    }
+
+    for (token, entry) in source.tokens()? {
+        target.insert_token(&token, &entry)?;
+        report.tokens += 1;
+    }
+
+    let mut emails = source.emails()?;
+    emails.sort_by_key(|email| email.pk);
+    for email in emails {
+        target.insert_email(&email)?;
+        report.emails += 1;
+    }
+
+    for post in source.posts()? {
+        target.insert_post(&post)?;
+        report.posts += 1;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SqliteStorage;
+    use std::fs;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_import_yaml_into_sqlite() {
+        let dir = Path::new("./target/test-data/import").join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("users.yaml"),
+            "jane@example.com:\n  first_name: Jane\n  last_name: Doe\n  email: jane@example.com\n  \
+             verified: true\n  stash: []\n  liked_posts: []\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("emails.yaml"),
+            "next_pk: 8\nemails:\n  7:\n    pk: 7\n    to: jane@example.com\n    subject: Hello\n    body: Hi\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("posts.yaml"),
+            format!("- id: {}\n  content: First post\n  image_path: null\n  likes: 1\n", Uuid::new_v4()),
+        )
+        .unwrap();
+
+        let target = SqliteStorage::in_memory().unwrap();
+        let report = import_yaml(&dir, &target).unwrap();
+        assert_eq!(report, ImportReport { users: 1, tokens: 0, emails: 1, posts: 1 });
+        assert!(target.get_user("jane@example.com").unwrap().unwrap().verified);
+        assert_eq!(target.posts().unwrap()[0].content, "First post");
+
+        // The importer is one-shot
+        assert!(import_yaml(&dir, &target).is_err());
+    }
+
+    #[test]
+    fn test_import_converts_the_single_passkey_of_baseline_users() {
+        let dir = Path::new("./target/test-data/import").join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy("tests/test_files/legacy_users.yaml", dir.join("users.yaml")).unwrap();
+
+        let target = SqliteStorage::in_memory().unwrap();
+        assert_eq!(import_yaml(&dir, &target).unwrap().users, 2);
+
+        let jane = target.get_user("jane@example.com").unwrap().unwrap();
+        assert_eq!(jane.passkeys.len(), 1);
+        assert_eq!(jane.passkeys[0].name, "Passkey");
+        assert_eq!(jane.passkeys[0].passkey.cred_id().as_ref(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(target.get_user("john@example.com").unwrap().unwrap().passkeys.is_empty());
+    }
+
+    #[test]
+    fn test_import_rejects_corrupted_yaml() {
+        let dir = Path::new("./target/test-data/import").join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("users.yaml"), "jane@example.com: [not, a, user\n").unwrap();
+
+        let target = SqliteStorage::in_memory().unwrap();
+        assert!(import_yaml(&dir, &target).is_err());
+        assert!(target.users().unwrap().is_empty());
+    }
+}
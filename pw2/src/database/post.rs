@@ -0,0 +1,277 @@
+//! Gestion des posts
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    str::FromStr,
+};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use super::{storage, unix_timestamp};
+
+/// Réaction d'un utilisateur à un post
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reaction {
+    Like,
+    Dislike,
+}
+
+/// Commentaire sur un post, ou réponse à un commentaire (un seul niveau de réponses)
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Comment {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>, // Commentaire auquel celui-ci répond
+    pub author: String,          // Email de l'auteur
+    pub created_at: u64,         // Timestamp UNIX de création
+    pub content: String,
+}
+
+/// Version redimensionnée de l'image d'un post
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ImageVariant {
+    pub path: String, // Chemin utilisé par le frontend
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Modèle représentant un post avec les réactions de chaque utilisateur
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Post {
+    pub id: Uuid,
+    #[serde(default)]
+    pub author: String, // Email de l'auteur, vide pour les posts créés avant l'ajout des auteurs
+    #[serde(default)]
+    pub created_at: u64, // Timestamp UNIX de création
+    #[serde(default)]
+    pub edited_at: Option<u64>, // Timestamp UNIX de la dernière modification
+    pub content: String,
+    pub image_path: Option<String>, // Plus grande version de l'image
+    #[serde(default)]
+    pub image_variants: Vec<ImageVariant>, // Versions de l'image, de la plus petite à la plus grande
+    #[serde(default)]
+    pub reactions: HashMap<String, Reaction>, // Réaction de chaque utilisateur, par email
+    #[serde(default)]
+    pub comments: Vec<Comment>, // Commentaires et réponses, dans leur ordre de création
+}
+
+impl Post {
+    /// Nombre d'utilisateurs ayant eu la réaction donnée
+    pub fn count(&self, reaction: Reaction) -> usize {
+        self.reactions.values().filter(|r| **r == reaction).count()
+    }
+
+    pub fn reaction_of(&self, email: &str) -> Option<Reaction> {
+        self.reactions.get(email).copied()
+    }
+
+    pub fn comment(&self, id: &Uuid) -> Option<&Comment> {
+        self.comments.iter().find(|comment| comment.id == *id)
+    }
+
+    /// Position du post dans le fil trié selon `sort`
+    pub fn feed_cursor(&self, sort: FeedSort) -> FeedCursor {
+        let score = match sort {
+            FeedSort::Newest => self.created_at as i64,
+            FeedSort::Likes => self.count(Reaction::Like) as i64,
+            FeedSort::Comments => self.comments.len() as i64,
+        };
+
+        FeedCursor {
+            score,
+            created_at: self.created_at,
+            id: self.id,
+        }
+    }
+
+    /// Chemins de tous les fichiers image référencés par le post
+    pub fn image_paths(&self) -> BTreeSet<&str> {
+        self.image_variants
+            .iter()
+            .map(|variant| variant.path.as_str())
+            .chain(self.image_path.as_deref())
+            .collect()
+    }
+
+    /// Vérifie qu'une réponse peut être faite au commentaire donné, qui ne doit pas être lui-même une réponse
+    pub fn can_reply_to(&self, parent_id: &Uuid) -> bool {
+        self.comment(parent_id).is_some_and(|parent| parent.parent_id.is_none())
+    }
+}
+
+/// Ordres de tri du fil, toujours décroissants
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedSort {
+    #[default]
+    Newest,   // Les plus récents
+    Likes,    // Les plus likés
+    Comments, // Les plus commentés
+}
+
+/// Position d'un post dans le fil : les posts sont triés par score, puis date de création, puis identifiant,
+/// de sorte que l'ordre soit total et qu'une page puisse reprendre après le dernier post de la précédente
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeedCursor {
+    pub score: i64,
+    pub created_at: u64,
+    pub id: Uuid,
+}
+
+impl fmt::Display for FeedCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}_{}", self.score, self.created_at, self.id)
+    }
+}
+
+impl FromStr for FeedCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '_');
+        let mut next = || parts.next().ok_or_else(|| anyhow!("Invalid cursor"));
+        Ok(Self {
+            score: next()?.parse()?,
+            created_at: next()?.parse()?,
+            id: next()?.parse()?,
+        })
+    }
+}
+
+/// Requête d'une page du fil
+#[derive(Clone, Debug)]
+pub struct FeedQuery {
+    pub sort: FeedSort,
+    pub author: Option<String>,     // Seuls les posts de cet auteur
+    pub after: Option<FeedCursor>,  // Seuls les posts situés après ce curseur
+    pub limit: usize,
+}
+
+impl FeedQuery {
+    /// Vérifie qu'un post fait partie du résultat de la requête, sans tenir compte de la limite
+    pub fn matches(&self, post: &Post) -> bool {
+        self.author.as_ref().is_none_or(|author| post.author == *author)
+            && self.after.is_none_or(|after| post.feed_cursor(self.sort) < after)
+    }
+}
+
+/// Page du fil, avec le curseur de la page suivante s'il y en a une
+pub struct Feed {
+    pub posts: Vec<Post>,
+    pub next_cursor: Option<FeedCursor>,
+}
+
+/// Enregistre un nouveau post de l'utilisateur donné, retourne son identifiant
+pub fn create(author: &str, content: &str, image_variants: Vec<ImageVariant>) -> Result<Uuid> {
+    let post = Post {
+        id: Uuid::new_v4(),
+        author: author.to_string(),
+        created_at: unix_timestamp(),
+        edited_at: None,
+        content: content.to_string(),
+        image_path: image_variants.last().map(|variant| variant.path.clone()),
+        image_variants,
+        reactions: HashMap::new(),
+        comments: Vec::new(),
+    };
+
+    storage().insert_post(&post)?;
+    Ok(post.id)
+}
+
+/// Retourne une page du fil
+pub fn feed(query: &FeedQuery) -> Result<Feed> {
+    // Un post de plus est demandé pour savoir s'il existe une page suivante
+    let mut posts = storage().feed(&FeedQuery {
+        limit: query.limit + 1,
+        ..query.clone()
+    })?;
+
+    let next_cursor = if posts.len() > query.limit {
+        posts.truncate(query.limit);
+        posts.last().map(|post| post.feed_cursor(query.sort))
+    } else {
+        None
+    };
+    Ok(Feed { posts, next_cursor })
+}
+
+pub fn get(id: &Uuid) -> Option<Post> {
+    storage().get_post(id).ok()?
+}
+
+/// Modifie le contenu d'un post
+pub fn edit(id: &Uuid, content: &str) -> Result<bool> {
+    update(id, |post| {
+        post.content = content.to_string();
+        post.edited_at = Some(unix_timestamp());
+        Ok(())
+    })
+}
+
+/// Applique la réaction d'un utilisateur, ou la retire si c'était déjà la sienne.
+/// Retourne le post mis à jour.
+pub fn react(id: &Uuid, email: &str, reaction: Reaction) -> Result<Post> {
+    let mut updated = None;
+    let found = update(id, |post| {
+        if post.reactions.get(email) == Some(&reaction) {
+            post.reactions.remove(email);
+        } else {
+            post.reactions.insert(email.to_string(), reaction);
+        }
+        updated = Some(post.clone());
+        Ok(())
+    })?;
+
+    updated.filter(|_| found).ok_or_else(|| anyhow!("Post not found"))
+}
+
+/// Ajoute un commentaire à un post, ou une réponse à un commentaire de premier niveau
+pub fn add_comment(post_id: &Uuid, author: &str, content: &str, parent_id: Option<Uuid>) -> Result<Comment> {
+    let comment = Comment {
+        id: Uuid::new_v4(),
+        parent_id,
+        author: author.to_string(),
+        created_at: unix_timestamp(),
+        content: content.to_string(),
+    };
+
+    let found = update(post_id, |post| {
+        if let Some(parent_id) = &comment.parent_id {
+            if !post.can_reply_to(parent_id) {
+                bail!("Invalid parent comment");
+            }
+        }
+        post.comments.push(comment.clone());
+        Ok(())
+    })?;
+
+    if !found {
+        bail!("Post not found");
+    }
+    Ok(comment)
+}
+
+/// Supprime un commentaire et ses réponses, retourne `false` s'il n'existait pas
+pub fn delete_comment(post_id: &Uuid, comment_id: &Uuid) -> Result<bool> {
+    let mut deleted = false;
+    update(post_id, |post| {
+        let count = post.comments.len();
+        post.comments
+            .retain(|comment| comment.id != *comment_id && comment.parent_id != Some(*comment_id));
+        deleted = post.comments.len() != count;
+        Ok(())
+    })?;
+    Ok(deleted)
+}
+
+/// Supprime un post, retourne `false` s'il n'existait pas
+pub fn delete(id: &Uuid) -> Result<bool> {
+    storage().delete_post(id)
+}
+
+/// Modifie un post, retourne `false` s'il n'existe pas
+pub fn update(id: &Uuid, mut update: impl FnMut(&mut Post) -> Result<()>) -> Result<bool> {
+    storage().update_post(id, &mut update)
+}
@@ -0,0 +1,51 @@
+//! Gestion des sessions persistées
+
+use std::io;
+use anyhow::Result;
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use tower_sessions::{session::Id, ExpiredDeletion, Session, SessionStore};
+use super::{storage, Storage};
+
+/// Store de sessions enregistrées dans le stockage configuré,
+/// de sorte que les sessions survivent à un redémarrage du serveur.
+#[derive(Clone, Debug, Default)]
+pub struct PersistentStore;
+
+/// Exécute une opération sur le stockage hors des threads de tokio, les accès au disque étant bloquants
+async fn blocking<R: Send + 'static>(
+    operation: impl FnOnce(&dyn Storage) -> Result<R> + Send + 'static,
+) -> Result<R, io::Error> {
+    tokio::task::spawn_blocking(move || operation(storage()))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(io::Error::other)
+}
+
+#[async_trait]
+impl SessionStore for PersistentStore {
+    type Error = io::Error;
+
+    async fn save(&self, session: &Session) -> Result<(), Self::Error> {
+        let session = session.clone();
+        blocking(move |storage| storage.save_session(&session)).await
+    }
+
+    async fn load(&self, session_id: &Id) -> Result<Option<Session>, Self::Error> {
+        let id = session_id.to_string();
+        blocking(move |storage| storage.get_session(&id, OffsetDateTime::now_utc())).await
+    }
+
+    async fn delete(&self, session_id: &Id) -> Result<(), Self::Error> {
+        let id = session_id.to_string();
+        blocking(move |storage| storage.delete_session(&id)).await
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for PersistentStore {
+    async fn delete_expired(&self) -> Result<(), Self::Error> {
+        blocking(|storage| storage.delete_expired_sessions(OffsetDateTime::now_utc())).await?;
+        Ok(())
+    }
+}
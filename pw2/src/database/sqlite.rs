@@ -0,0 +1,679 @@
+//! Stockage dans une base SQLite embarquée.
+//! Chaque opération est exécutée dans une transaction, et le schéma est mis à jour au démarrage
+//! par les migrations de `MIGRATIONS`, dont la dernière appliquée est indiquée par `user_version`.
+
+use std::{collections::HashSet, fs::create_dir_all, path::Path, sync::Mutex};
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use uuid::Uuid;
+use super::{
+    email::Email,
+    post::{FeedQuery, FeedSort, Post, Reaction},
+    storage::Storage,
+    token::Token,
+    user::User,
+};
+
+/// Migrations du schéma, dans l'ordre, ne doivent jamais être modifiées une fois publiées
+const MIGRATIONS: &[&str] = &[
+    // 1: Schéma initial, les structures imbriquées sont enregistrées en JSON
+    "CREATE TABLE users (
+        email TEXT PRIMARY KEY NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE emails (
+        pk INTEGER PRIMARY KEY AUTOINCREMENT,
+        recipient TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        body TEXT NOT NULL,
+        html_body TEXT
+    );
+    CREATE TABLE posts (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT NOT NULL UNIQUE,
+        data TEXT NOT NULL
+    );",
+    // 2: Colonnes dénormalisées et index pour la pagination du fil
+    "ALTER TABLE posts ADD COLUMN author TEXT NOT NULL DEFAULT '';
+    ALTER TABLE posts ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE posts ADD COLUMN likes INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE posts ADD COLUMN comments INTEGER NOT NULL DEFAULT 0;
+    UPDATE posts SET
+        author = coalesce(json_extract(data, '$.author'), ''),
+        created_at = coalesce(json_extract(data, '$.created_at'), 0),
+        likes = (SELECT count(*) FROM json_each(posts.data, '$.reactions') WHERE json_each.value = 'like'),
+        comments = coalesce(json_array_length(data, '$.comments'), 0);
+    CREATE INDEX posts_by_newest ON posts (created_at, id);
+    CREATE INDEX posts_by_likes ON posts (likes, created_at, id);
+    CREATE INDEX posts_by_comments ON posts (comments, created_at, id);
+    CREATE INDEX posts_by_author ON posts (author, created_at, id);",
+    // 3: Références des posts vers les fichiers image, pour compter leurs utilisations
+    "CREATE TABLE post_images (
+        post_id TEXT NOT NULL,
+        path TEXT NOT NULL,
+        PRIMARY KEY (post_id, path)
+    );
+    CREATE INDEX post_images_by_path ON post_images (path);
+    INSERT OR IGNORE INTO post_images (post_id, path)
+        SELECT id, json_extract(data, '$.image_path') FROM posts
+        WHERE json_extract(data, '$.image_path') IS NOT NULL;
+    INSERT OR IGNORE INTO post_images (post_id, path)
+        SELECT posts.id, json_extract(variant.value, '$.path') FROM posts, json_each(posts.data, '$.image_variants') AS variant;",
+    // 4: Sessions, une ligne par session pour que chaque requête n'en réécrive qu'une
+    "CREATE TABLE sessions (
+        id TEXT PRIMARY KEY NOT NULL,
+        data TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX sessions_by_expiry ON sessions (expires_at);",
+    // 5: Identifiant WebAuthn des utilisateurs, pour retrouver le propriétaire d'une passkey découvrable
+    "ALTER TABLE users ADD COLUMN user_handle TEXT;
+    UPDATE users SET user_handle = json_extract(data, '$.user_handle');
+    CREATE UNIQUE INDEX users_by_handle ON users (user_handle);",
+];
+
+/// Stockage dans une base SQLite
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Ouvre (ou crée) la base de données et applique les migrations manquantes
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent_dir) = path.parent() {
+            create_dir_all(parent_dir).context("Failed to create directory")?;
+        }
+
+        let conn = Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        Self::with_connection(conn)
+    }
+
+    /// Base de données temporaire, en mémoire
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Exécute `f` dans une transaction, validée seulement si `f` réussit
+    fn transaction<R>(&self, f: impl FnOnce(&Transaction) -> Result<R>) -> Result<R> {
+        let mut conn = self.conn.lock().or(Err(anyhow!("DB poisoned")))?;
+        let tx = conn.transaction()?;
+        let result = f(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+}
+
+/// Applique les migrations qui ne l'ont pas encore été
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        bail!("Database schema version {} is newer than this application", version);
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to apply migration {}", index + 1))?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).context("Failed to serialize record")
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> Result<T> {
+    serde_json::from_str(data).context("Corrupted record")
+}
+
+/// Lit une colonne `data` JSON pour chaque ligne retournée par la requête
+fn query_json<T: DeserializeOwned>(tx: &Transaction, sql: &str, params: impl rusqlite::Params) -> Result<Vec<T>> {
+    let mut stmt = tx.prepare(sql)?;
+    let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+    rows.map(|data| from_json(&data?)).collect()
+}
+
+fn email_from_row(row: &rusqlite::Row) -> rusqlite::Result<Email> {
+    Ok(Email {
+        pk: row.get::<_, i64>(0)? as u64,
+        to: row.get(1)?,
+        subject: row.get(2)?,
+        body: row.get(3)?,
+        html_body: row.get(4)?,
+    })
+}
+
+/// Enregistre un post et ses colonnes dénormalisées, sans changer sa position (`seq`) s'il existe déjà
+fn write_post(tx: &Transaction, post: &Post) -> Result<()> {
+    tx.execute(
+        "INSERT INTO posts (id, data, author, created_at, likes, comments) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT (id) DO UPDATE SET
+            data = excluded.data,
+            author = excluded.author,
+            created_at = excluded.created_at,
+            likes = excluded.likes,
+            comments = excluded.comments",
+        params![
+            post.id.to_string(),
+            to_json(post)?,
+            post.author,
+            post.created_at as i64,
+            post.count(Reaction::Like) as i64,
+            post.comments.len() as i64,
+        ],
+    )?;
+
+    tx.execute("DELETE FROM post_images WHERE post_id = ?1", [post.id.to_string()])?;
+    for path in post.image_paths() {
+        tx.execute(
+            "INSERT INTO post_images (post_id, path) VALUES (?1, ?2)",
+            params![post.id.to_string(), path],
+        )?;
+    }
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn insert_user(&self, user: &User) -> Result<bool> {
+        self.transaction(|tx| {
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO users (email, data, user_handle) VALUES (?1, ?2, ?3)",
+                params![user.email, to_json(user)?, user.user_handle.map(|handle| handle.to_string())],
+            )?;
+            Ok(inserted == 1)
+        })
+    }
+
+    fn get_user(&self, email: &str) -> Result<Option<User>> {
+        self.transaction(|tx| {
+            Ok(query_json(tx, "SELECT data FROM users WHERE email = ?1", [email])?.pop())
+        })
+    }
+
+    fn users(&self) -> Result<Vec<User>> {
+        self.transaction(|tx| query_json(tx, "SELECT data FROM users ORDER BY email", []))
+    }
+
+    fn user_by_handle(&self, handle: &Uuid) -> Result<Option<User>> {
+        self.transaction(|tx| {
+            Ok(query_json(tx, "SELECT data FROM users WHERE user_handle = ?1", [handle.to_string()])?.pop())
+        })
+    }
+
+    fn update_user(&self, email: &str, update: &mut dyn FnMut(&mut User) -> Result<()>) -> Result<bool> {
+        self.transaction(|tx| {
+            let Some(mut user) = query_json::<User>(tx, "SELECT data FROM users WHERE email = ?1", [email])?.pop()
+            else {
+                return Ok(false);
+            };
+
+            update(&mut user)?;
+            tx.execute(
+                "UPDATE users SET data = ?2, user_handle = ?3 WHERE email = ?1",
+                params![email, to_json(&user)?, user.user_handle.map(|handle| handle.to_string())],
+            )?;
+            Ok(true)
+        })
+    }
+
+    fn insert_token(&self, token: &str, entry: &Token) -> Result<()> {
+        self.transaction(|tx| {
+            tx.execute(
+                "INSERT OR REPLACE INTO tokens (token, data) VALUES (?1, ?2)",
+                params![token, to_json(entry)?],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn take_token(&self, token: &str, predicate: &dyn Fn(&Token) -> bool) -> Result<Option<Token>> {
+        self.transaction(|tx| {
+            let entry = query_json::<Token>(tx, "SELECT data FROM tokens WHERE token = ?1", [token])?
+                .pop()
+                .filter(|entry| predicate(entry));
+
+            if entry.is_some() {
+                tx.execute("DELETE FROM tokens WHERE token = ?1", [token])?;
+            }
+            Ok(entry)
+        })
+    }
+
+    fn tokens(&self) -> Result<Vec<(String, Token)>> {
+        self.transaction(|tx| {
+            let mut stmt = tx.prepare("SELECT token, data FROM tokens")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            rows.map(|row| {
+                let (token, data) = row?;
+                Ok((token, from_json(&data)?))
+            })
+            .collect()
+        })
+    }
+
+    fn retain_tokens(&self, keep: &dyn Fn(&Token) -> bool) -> Result<usize> {
+        self.transaction(|tx| {
+            let mut stmt = tx.prepare("SELECT token, data FROM tokens")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+            let mut removed = 0;
+            for row in rows {
+                let (token, data) = row?;
+                if !keep(&from_json(&data)?) {
+                    removed += tx.execute("DELETE FROM tokens WHERE token = ?1", [token])?;
+                }
+            }
+            Ok(removed)
+        })
+    }
+
+    fn insert_email(&self, email: &Email) -> Result<u64> {
+        self.transaction(|tx| {
+            tx.execute(
+                "INSERT INTO emails (recipient, subject, body, html_body) VALUES (?1, ?2, ?3, ?4)",
+                params![email.to, email.subject, email.body, email.html_body],
+            )?;
+            Ok(tx.last_insert_rowid() as u64)
+        })
+    }
+
+    fn get_email(&self, pk: u64) -> Result<Option<Email>> {
+        self.transaction(|tx| {
+            Ok(tx
+                .query_row(
+                    "SELECT pk, recipient, subject, body, html_body FROM emails WHERE pk = ?1",
+                    [pk as i64],
+                    email_from_row,
+                )
+                .optional()?)
+        })
+    }
+
+    fn emails(&self) -> Result<Vec<Email>> {
+        self.transaction(|tx| {
+            let mut stmt = tx.prepare("SELECT pk, recipient, subject, body, html_body FROM emails")?;
+            let emails = stmt.query_map([], email_from_row)?.collect::<rusqlite::Result<_>>()?;
+            Ok(emails)
+        })
+    }
+
+    fn delete_email(&self, pk: u64) -> Result<bool> {
+        self.transaction(|tx| Ok(tx.execute("DELETE FROM emails WHERE pk = ?1", [pk as i64])? == 1))
+    }
+
+    fn insert_post(&self, post: &Post) -> Result<()> {
+        self.transaction(|tx| {
+            let exists = tx
+                .query_row("SELECT 1 FROM posts WHERE id = ?1", [post.id.to_string()], |_| Ok(()))
+                .optional()?;
+            if exists.is_some() {
+                bail!("Post already exists");
+            }
+            write_post(tx, post)
+        })
+    }
+
+    fn get_post(&self, id: &Uuid) -> Result<Option<Post>> {
+        self.transaction(|tx| Ok(query_json(tx, "SELECT data FROM posts WHERE id = ?1", [id.to_string()])?.pop()))
+    }
+
+    fn posts(&self) -> Result<Vec<Post>> {
+        self.transaction(|tx| query_json(tx, "SELECT data FROM posts ORDER BY seq", []))
+    }
+
+    fn update_post(&self, id: &Uuid, update: &mut dyn FnMut(&mut Post) -> Result<()>) -> Result<bool> {
+        let id = id.to_string();
+        self.transaction(|tx| {
+            let Some(mut post) = query_json::<Post>(tx, "SELECT data FROM posts WHERE id = ?1", [&id])?.pop() else {
+                return Ok(false);
+            };
+
+            update(&mut post)?;
+            write_post(tx, &post)?;
+            Ok(true)
+        })
+    }
+
+    fn feed(&self, query: &FeedQuery) -> Result<Vec<Post>> {
+        let score = match query.sort {
+            FeedSort::Newest => "created_at",
+            FeedSort::Likes => "likes",
+            FeedSort::Comments => "comments",
+        };
+
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        if let Some(author) = &query.author {
+            values.push(Box::new(author.clone()));
+            conditions.push(format!("author = ?{}", values.len()));
+        }
+        if let Some(after) = &query.after {
+            values.push(Box::new(after.score));
+            values.push(Box::new(after.created_at as i64));
+            values.push(Box::new(after.id.to_string()));
+            let n = values.len();
+            conditions.push(format!("({}, created_at, id) < (?{}, ?{}, ?{})", score, n - 2, n - 1, n));
+        }
+        values.push(Box::new(query.limit as i64));
+
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = format!(
+            "SELECT data FROM posts {} ORDER BY {} DESC, created_at DESC, id DESC LIMIT ?{}",
+            filter,
+            score,
+            values.len()
+        );
+
+        self.transaction(|tx| query_json(tx, &sql, rusqlite::params_from_iter(values.iter())))
+    }
+
+    fn delete_post(&self, id: &Uuid) -> Result<bool> {
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM post_images WHERE post_id = ?1", [id.to_string()])?;
+            Ok(tx.execute("DELETE FROM posts WHERE id = ?1", [id.to_string()])? == 1)
+        })
+    }
+
+    fn image_references(&self, path: &str) -> Result<usize> {
+        self.transaction(|tx| {
+            let count: i64 = tx.query_row("SELECT count(*) FROM post_images WHERE path = ?1", [path], |row| row.get(0))?;
+            Ok(count as usize)
+        })
+    }
+
+    fn referenced_images(&self) -> Result<HashSet<String>> {
+        self.transaction(|tx| {
+            let mut statement = tx.prepare("SELECT DISTINCT path FROM post_images")?;
+            let paths = statement.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+            Ok(paths)
+        })
+    }
+
+    fn save_session(&self, session: &Session) -> Result<()> {
+        self.transaction(|tx| {
+            tx.execute(
+                "INSERT INTO sessions (id, data, expires_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+                params![session.id().to_string(), to_json(session)?, session.expiry_date().unix_timestamp()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn get_session(&self, id: &str, now: OffsetDateTime) -> Result<Option<Session>> {
+        self.transaction(|tx| {
+            Ok(query_json(
+                tx,
+                "SELECT data FROM sessions WHERE id = ?1 AND expires_at > ?2",
+                params![id, now.unix_timestamp()],
+            )?
+            .pop())
+        })
+    }
+
+    fn delete_session(&self, id: &str) -> Result<()> {
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM sessions WHERE id = ?1", [id])?;
+            Ok(())
+        })
+    }
+
+    fn delete_expired_sessions(&self, now: OffsetDateTime) -> Result<usize> {
+        self.transaction(|tx| Ok(tx.execute("DELETE FROM sessions WHERE expires_at <= ?1", [now.unix_timestamp()])?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::post::{Comment, ImageVariant};
+    use std::collections::HashMap;
+    use crate::database::token::Purpose;
+    use tower_sessions::Expiry;
+
+    fn user(email: &str) -> User {
+        User {
+            first_name: "Jane".to_string(),
+            last_name: "Doe".to_string(),
+            email: email.to_string(),
+            passkeys: Vec::new(),
+            verified: false,
+            stash: Vec::new(),
+            user_handle: None,
+        }
+    }
+
+    #[test]
+    fn test_migrations_are_applied_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_sessions_are_upserted_and_expire() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let active = Session::new(Some(Expiry::AtDateTime(now + time::Duration::hours(1))));
+        let expired = Session::new(Some(Expiry::AtDateTime(now - time::Duration::minutes(1))));
+        active.insert("visits", 1).unwrap();
+        storage.save_session(&active).unwrap();
+        active.insert("visits", 2).unwrap();
+        storage.save_session(&active).unwrap();
+        storage.save_session(&expired).unwrap();
+
+        let id = active.id().to_string();
+        let loaded = storage.get_session(&id, now).unwrap().unwrap();
+        assert_eq!(loaded.get::<u32>("visits").unwrap(), Some(2));
+        assert!(storage.get_session(&expired.id().to_string(), now).unwrap().is_none());
+
+        assert_eq!(storage.delete_expired_sessions(now).unwrap(), 1);
+        storage.delete_session(&id).unwrap();
+        assert!(storage.get_session(&id, now).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_users_are_found_by_their_handle() {
+        // Users stored before the handle column existed are indexed by the migration
+        let mut conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..4] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 4).unwrap();
+        let handle = Uuid::new_v4();
+        let jane = User { user_handle: Some(handle), ..user("jane@example.com") };
+        conn.execute(
+            "INSERT INTO users (email, data) VALUES (?1, ?2)",
+            params![jane.email, to_json(&jane).unwrap()],
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+        let storage = SqliteStorage::with_connection(conn).unwrap();
+        assert_eq!(storage.user_by_handle(&handle).unwrap().unwrap().email, "jane@example.com");
+
+        // Handles assigned later are indexed too, and cannot be shared
+        let other = Uuid::new_v4();
+        assert!(storage.insert_user(&user("john@example.com")).unwrap());
+        assert!(storage.user_by_handle(&other).unwrap().is_none());
+        storage.update_user("john@example.com", &mut |user| {
+            user.user_handle = Some(other);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(storage.user_by_handle(&other).unwrap().unwrap().email, "john@example.com");
+        assert!(storage.update_user("john@example.com", &mut |user| {
+            user.user_handle = Some(handle);
+            Ok(())
+        })
+        .is_err());
+        assert!(!storage.insert_user(&User { user_handle: Some(handle), ..user("joe@example.com") }).unwrap());
+    }
+
+    #[test]
+    fn test_failed_update_is_rolled_back() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        assert!(storage.insert_user(&user("jane@example.com")).unwrap());
+        assert!(!storage.insert_user(&user("jane@example.com")).unwrap());
+
+        let result = storage.update_user("jane@example.com", &mut |user| {
+            user.verified = true;
+            bail!("Refused")
+        });
+        assert!(result.is_err());
+        assert!(!storage.get_user("jane@example.com").unwrap().unwrap().verified);
+
+        assert!(storage.update_user("jane@example.com", &mut |user| {
+            user.verified = true;
+            Ok(())
+        })
+        .unwrap());
+        assert!(storage.get_user("jane@example.com").unwrap().unwrap().verified);
+        assert!(!storage.update_user("john@example.com", &mut |_| Ok(())).unwrap());
+    }
+
+    #[test]
+    fn test_token_is_taken_only_when_predicate_matches() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let entry = Token {
+            email: "jane@example.com".to_string(),
+            purpose: Purpose::Recovery,
+            issued_at: 0,
+            ttl: 60,
+        };
+        storage.insert_token("abc", &entry).unwrap();
+
+        assert!(storage.take_token("abc", &|t| t.purpose == Purpose::Validation).unwrap().is_none());
+        assert!(storage.take_token("abc", &|t| t.purpose == Purpose::Recovery).unwrap().is_some());
+        assert!(storage.take_token("abc", &|_| true).unwrap().is_none());
+    }
+
+    /// Parcourt tout le fil page par page et retourne les identifiants obtenus
+    fn walk_feed(storage: &dyn Storage, sort: FeedSort, author: Option<&str>) -> Vec<Uuid> {
+        let mut query = FeedQuery {
+            sort,
+            author: author.map(str::to_string),
+            after: None,
+            limit: 2,
+        };
+        let mut ids = Vec::new();
+        loop {
+            let page = storage.feed(&query).unwrap();
+            let Some(last) = page.last() else {
+                return ids;
+            };
+            query.after = Some(last.feed_cursor(sort));
+            ids.extend(page.iter().map(|post| post.id));
+        }
+    }
+
+    #[test]
+    fn test_feed_matches_the_default_implementation() {
+        let dir = std::path::Path::new("./target/test-data/feed").join(Uuid::new_v4().to_string());
+        let yaml = crate::database::YamlStorage::open(&dir).unwrap();
+        let sqlite = SqliteStorage::in_memory().unwrap();
+
+        // Equal dates and counters exercise the tie-breaking
+        for i in 0..7u64 {
+            let author = if i % 2 == 0 { "jane@example.com" } else { "john@example.com" };
+            let post = Post {
+                id: Uuid::new_v4(),
+                author: author.to_string(),
+                created_at: i / 2,
+                edited_at: None,
+                content: format!("Post {}", i),
+                image_path: None,
+                image_variants: Vec::new(),
+                reactions: (0..i % 3).map(|n| (format!("fan{}@example.com", n), Reaction::Like)).collect(),
+                comments: (0..i % 4)
+                    .map(|n| Comment {
+                        id: Uuid::new_v4(),
+                        parent_id: None,
+                        author: author.to_string(),
+                        created_at: n,
+                        content: "Hi".to_string(),
+                    })
+                    .collect(),
+            };
+            yaml.insert_post(&post).unwrap();
+            sqlite.insert_post(&post).unwrap();
+        }
+
+        for sort in [FeedSort::Newest, FeedSort::Likes, FeedSort::Comments] {
+            for author in [None, Some("jane@example.com")] {
+                let ids = walk_feed(&sqlite, sort, author);
+                assert_eq!(ids, walk_feed(&yaml, sort, author), "{:?} {:?}", sort, author);
+                assert_eq!(ids.len(), if author.is_some() { 4 } else { 7 });
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_references_follow_the_posts() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let variant = |path: &str| ImageVariant {
+            path: path.to_string(),
+            width: 1,
+            height: 1,
+        };
+        let mut post = Post {
+            id: Uuid::new_v4(),
+            author: "jane@example.com".to_string(),
+            created_at: 0,
+            edited_at: None,
+            content: "Shared".to_string(),
+            image_path: Some("/uploads/b.png".to_string()),
+            image_variants: vec![variant("/uploads/a.png"), variant("/uploads/b.png")],
+            reactions: HashMap::new(),
+            comments: Vec::new(),
+        };
+        storage.insert_post(&post).unwrap();
+        post.id = Uuid::new_v4();
+        post.image_variants.pop();
+        storage.insert_post(&post).unwrap();
+
+        assert_eq!(storage.image_references("/uploads/a.png").unwrap(), 2);
+        assert_eq!(storage.image_references("/uploads/b.png").unwrap(), 2);
+        assert_eq!(storage.referenced_images().unwrap().len(), 2);
+
+        storage
+            .update_post(&post.id, &mut |post| {
+                post.image_path = None;
+                post.image_variants.clear();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(storage.image_references("/uploads/a.png").unwrap(), 1);
+        let first = storage.posts().unwrap()[0].id;
+        assert!(storage.delete_post(&first).unwrap());
+        assert!(storage.referenced_images().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Interface commune aux backends de stockage.
+//! Chaque opération est atomique : une modification est entièrement enregistrée ou pas du tout.
+
+use std::{cmp::Reverse, collections::HashSet};
+use anyhow::Result;
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use uuid::Uuid;
+use super::{
+    email::Email,
+    post::{FeedQuery, Post},
+    token::Token,
+    user::User,
+};
+
+pub trait Storage: Send + Sync {
+    /// Ajoute un utilisateur, retourne `false` si l'email est déjà utilisé
+    fn insert_user(&self, user: &User) -> Result<bool>;
+    fn get_user(&self, email: &str) -> Result<Option<User>>;
+    fn users(&self) -> Result<Vec<User>>;
+    /// Retrouve l'utilisateur possédant l'identifiant WebAuthn donné.
+    /// L'implémentation par défaut parcourt tous les utilisateurs.
+    fn user_by_handle(&self, handle: &Uuid) -> Result<Option<User>> {
+        Ok(self
+            .users()?
+            .into_iter()
+            .find(|user| user.user_handle.as_ref() == Some(handle)))
+    }
+    /// Modifie un utilisateur, rien n'est enregistré si `update` échoue.
+    /// Retourne `false` si l'utilisateur n'existe pas.
+    fn update_user(&self, email: &str, update: &mut dyn FnMut(&mut User) -> Result<()>) -> Result<bool>;
+
+    fn insert_token(&self, token: &str, entry: &Token) -> Result<()>;
+    /// Retire un token et le retourne, uniquement s'il satisfait `predicate`
+    fn take_token(&self, token: &str, predicate: &dyn Fn(&Token) -> bool) -> Result<Option<Token>>;
+    fn tokens(&self) -> Result<Vec<(String, Token)>>;
+    /// Supprime les tokens ne satisfaisant pas `keep`, retourne le nombre de tokens supprimés
+    fn retain_tokens(&self, keep: &dyn Fn(&Token) -> bool) -> Result<usize>;
+
+    /// Ajoute un email et retourne l'identifiant qui lui a été attribué (le champ `pk` est ignoré)
+    fn insert_email(&self, email: &Email) -> Result<u64>;
+    fn get_email(&self, pk: u64) -> Result<Option<Email>>;
+    fn emails(&self) -> Result<Vec<Email>>;
+    /// Supprime un email, retourne `false` s'il n'existait pas
+    fn delete_email(&self, pk: u64) -> Result<bool>;
+
+    fn insert_post(&self, post: &Post) -> Result<()>;
+    fn get_post(&self, id: &Uuid) -> Result<Option<Post>>;
+    /// Retourne les posts dans leur ordre de création
+    fn posts(&self) -> Result<Vec<Post>>;
+    /// Retourne les posts correspondant à la requête, dans l'ordre du fil.
+    /// L'implémentation par défaut parcourt tous les posts.
+    fn feed(&self, query: &FeedQuery) -> Result<Vec<Post>> {
+        let mut posts = self
+            .posts()?
+            .into_iter()
+            .filter(|post| query.matches(post))
+            .collect::<Vec<_>>();
+        posts.sort_by_key(|post| Reverse(post.feed_cursor(query.sort)));
+        posts.truncate(query.limit);
+        Ok(posts)
+    }
+    /// Modifie un post, rien n'est enregistré si `update` échoue.
+    /// Retourne `false` si le post n'existe pas.
+    fn update_post(&self, id: &Uuid, update: &mut dyn FnMut(&mut Post) -> Result<()>) -> Result<bool>;
+    /// Supprime un post, retourne `false` s'il n'existait pas
+    fn delete_post(&self, id: &Uuid) -> Result<bool>;
+
+    /// Nombre de posts référençant le fichier image donné.
+    /// L'implémentation par défaut parcourt tous les posts.
+    fn image_references(&self, path: &str) -> Result<usize> {
+        Ok(self
+            .posts()?
+            .iter()
+            .filter(|post| post.image_paths().contains(path))
+            .count())
+    }
+    /// Enregistre une session, en remplaçant sa version précédente
+    fn save_session(&self, session: &Session) -> Result<()>;
+    /// Retourne une session, uniquement si elle n'a pas expiré
+    fn get_session(&self, id: &str, now: OffsetDateTime) -> Result<Option<Session>>;
+    fn delete_session(&self, id: &str) -> Result<()>;
+    /// Supprime les sessions expirées, retourne leur nombre
+    fn delete_expired_sessions(&self, now: OffsetDateTime) -> Result<usize>;
+
+    /// Chemins de tous les fichiers image référencés par au moins un post
+    fn referenced_images(&self) -> Result<HashSet<String>> {
+        Ok(self
+            .posts()?
+            .iter()
+            .flat_map(|post| post.image_paths().into_iter().map(str::to_string).collect::<Vec<_>>())
+            .collect())
+    }
+}
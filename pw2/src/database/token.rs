@@ -0,0 +1,71 @@
+//! Gestion des tokens
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use crate::consts;
+use super::{storage, unix_timestamp};
+
+/// Usage auquel un token est destiné, un token ne peut être consommé que pour cet usage
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Purpose {
+    Validation, // Validation de l'adresse email d'un nouveau compte
+    Recovery,   // Récupération d'un compte
+}
+
+impl Purpose {
+    /// Durée de validité d'un token, en secondes
+    fn ttl(self) -> u64 {
+        match self {
+            Purpose::Validation => consts::VALIDATION_TOKEN_TTL_SECS,
+            Purpose::Recovery => consts::RECOVERY_TOKEN_TTL_SECS,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Token {
+    pub email: String,
+    pub purpose: Purpose,
+    pub issued_at: u64, // Timestamp UNIX d'émission
+    pub ttl: u64,       // Durée de validité en secondes
+}
+
+impl Token {
+    fn is_expired(&self, now: u64) -> bool {
+        now >= self.issued_at.saturating_add(self.ttl)
+    }
+}
+
+pub fn generate(email: &str, purpose: Purpose) -> Result<String> {
+    let token = uuid::Uuid::new_v4().to_string();
+    let entry = Token {
+        email: email.to_string(),
+        purpose,
+        issued_at: unix_timestamp(),
+        ttl: purpose.ttl(),
+    };
+
+    storage().insert_token(&token, &entry)?;
+    Ok(token)
+}
+
+/// Consomme un token et retourne l'email associé, à condition que le token soit destiné à
+/// l'usage demandé et qu'il ne soit pas expiré
+pub fn consume(token: &str, purpose: Purpose) -> Result<String> {
+    // A token presented for another purpose is left untouched
+    let entry = storage()
+        .take_token(token, &|entry| entry.purpose == purpose)?
+        .ok_or_else(|| anyhow!("Token not found"))?;
+
+    if entry.is_expired(unix_timestamp()) {
+        return Err(anyhow!("Token expired"));
+    }
+    Ok(entry.email)
+}
+
+/// Supprime les tokens expirés, retourne le nombre de tokens supprimés
+pub fn sweep_expired() -> Result<usize> {
+    let now = unix_timestamp();
+    storage().retain_tokens(&|entry| !entry.is_expired(now))
+}
@@ -0,0 +1,177 @@
+//! Fichiers image uploadés, enregistrés sous l'empreinte SHA-256 de leur contenu.
+//! Une même image n'est enregistrée qu'une fois, et son fichier est supprimé lorsque plus
+//! aucun post n'y fait référence.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime},
+};
+use anyhow::{Context, Result};
+use log::warn;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use super::storage;
+use crate::{config, consts};
+
+/// Préfixe des chemins utilisés par le frontend
+pub const PUBLIC_PREFIX: &str = "/uploads/";
+
+/// Sérialise l'ajout et le retrait de références, pour qu'un fichier ne soit pas supprimé
+/// entre son écriture et l'enregistrement du post qui l'utilise
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Verrou à tenir pendant l'enregistrement d'un fichier et du post qui le référence,
+/// ainsi que pendant la suppression d'un post et de ses fichiers
+pub fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn uploads_dir() -> PathBuf {
+    config::get().data_path(consts::UPLOADS_DIR)
+}
+
+/// Nom du fichier désigné par un chemin du frontend, s'il est valide
+fn file_name(path: &str) -> Option<&str> {
+    path.strip_prefix(PUBLIC_PREFIX)
+        .filter(|name| !name.is_empty() && !name.contains(['/', '\\']) && !name.starts_with('.'))
+}
+
+/// Fichier désigné par un chemin du frontend, s'il est valide
+pub fn file_path(path: &str) -> Option<PathBuf> {
+    file_name(path).map(|name| uploads_dir().join(name))
+}
+
+/// Vérifie qu'un fichier est utilisé par au moins un post
+pub fn is_referenced(path: &str) -> Result<bool> {
+    Ok(storage().image_references(path)? > 0)
+}
+
+/// Enregistre un fichier sous l'empreinte de son contenu, retourne le chemin utilisé par le frontend.
+/// Un fichier identique déjà présent est réutilisé.
+pub fn store(bytes: &[u8], extension: &str) -> Result<String> {
+    let dir = uploads_dir();
+    fs::create_dir_all(&dir).context("Failed to create the uploads directory")?;
+
+    let name = format!("{}.{}", hex::encode(Sha256::digest(bytes)), extension);
+    let path = dir.join(&name);
+    if !path.exists() {
+        // Écriture dans un fichier temporaire, pour ne jamais exposer un fichier incomplet
+        let tmp_path = dir.join(format!(".{}.{}.tmp", name, Uuid::new_v4()));
+        fs::write(&tmp_path, bytes).with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        if let Err(e) = fs::rename(&tmp_path, &path) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+        }
+    }
+
+    Ok(format!("{}{}", PUBLIC_PREFIX, name))
+}
+
+/// Supprime les fichiers donnés qui ne sont plus référencés par aucun post,
+/// retourne le nombre de fichiers supprimés
+pub fn release<'a>(paths: impl IntoIterator<Item = &'a str>) -> Result<usize> {
+    let mut removed = 0;
+    for path in paths {
+        let Some(name) = file_name(path) else {
+            continue;
+        };
+        if is_referenced(path)? {
+            continue;
+        }
+
+        let file_path = uploads_dir().join(name);
+        match fs::remove_file(&file_path) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove {}: {}", file_path.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+/// Supprime les fichiers du dossier des uploads qui ne sont référencés par aucun post.
+/// Les fichiers récents sont conservés, ils peuvent appartenir à un post en cours de création
+/// par un autre processus. Retourne le nombre de fichiers supprimés.
+pub fn collect_garbage() -> Result<usize> {
+    let dir = uploads_dir();
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let _guard = lock();
+    let referenced = storage().referenced_images()?;
+    let grace_period = Duration::from_secs(consts::UPLOAD_GC_GRACE_SECS);
+    let mut removed = 0;
+    for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let path = format!("{}{}", PUBLIC_PREFIX, entry.file_name().to_string_lossy());
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if referenced.contains(&path) || age < grace_period {
+            continue;
+        }
+
+        match fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove {}: {}", entry.path().display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::post::{self, ImageVariant};
+    use std::fs::File;
+
+    #[test]
+    fn test_identical_files_are_stored_once() {
+        let content = Uuid::new_v4().to_string();
+        let path = store(content.as_bytes(), "png").unwrap();
+        assert_eq!(store(content.as_bytes(), "png").unwrap(), path);
+        assert_eq!(fs::read(uploads_dir().join(file_name(&path).unwrap())).unwrap(), content.as_bytes());
+        assert_ne!(store(b"other content", "png").unwrap(), path);
+    }
+
+    #[test]
+    fn test_garbage_collection_keeps_referenced_and_recent_files() {
+        let referenced = store(Uuid::new_v4().as_bytes(), "png").unwrap();
+        let variant = ImageVariant {
+            path: referenced.clone(),
+            width: 1,
+            height: 1,
+        };
+        post::create("gc@example.com", "Referenced", vec![variant]).unwrap();
+        let recent = store(Uuid::new_v4().as_bytes(), "png").unwrap();
+        let orphan = store(Uuid::new_v4().as_bytes(), "png").unwrap();
+
+        // Every file is backdated except the recent one
+        let old = SystemTime::now() - Duration::from_secs(consts::UPLOAD_GC_GRACE_SECS + 60);
+        for path in [&referenced, &orphan] {
+            let file = File::options().write(true).open(uploads_dir().join(file_name(path).unwrap())).unwrap();
+            file.set_modified(old).unwrap();
+        }
+
+        assert!(collect_garbage().unwrap() >= 1);
+        assert!(uploads_dir().join(file_name(&referenced).unwrap()).exists());
+        assert!(uploads_dir().join(file_name(&recent).unwrap()).exists());
+        assert!(!uploads_dir().join(file_name(&orphan).unwrap()).exists());
+    }
+
+    #[test]
+    fn test_paths_outside_the_uploads_directory_are_ignored() {
+        assert_eq!(file_name("/uploads/../lab02.sqlite3"), None);
+        assert_eq!(file_name("/uploads/a/b.png"), None);
+        assert_eq!(file_name("/static/a.png"), None);
+        assert_eq!(file_name("/uploads/a.png"), Some("a.png"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Gestion des utilisateurs
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use webauthn_rs::prelude::{AuthenticationResult, CredentialID, Passkey};
+use super::storage;
+
+/// Nom donné à une passkey lorsque l'utilisateur n'en a pas choisi
+pub const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+
+/// Passkey enregistrée par un utilisateur, identifiée par un nom choisi par ce dernier
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UserPasskey {
+    pub name: String,
+    pub passkey: Passkey,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(from = "StoredUser")]
+pub struct User {
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    #[serde(default)]
+    pub passkeys: Vec<UserPasskey>,
+    pub verified: bool,
+    pub stash: Vec<String>,
+    #[serde(default)]
+    pub user_handle: Option<Uuid>, // Identifiant WebAuthn de l'utilisateur, renvoyé par ses passkeys découvrables
+}
+
+impl User {
+    /// Indique si la passkey donnée appartient à l'utilisateur
+    pub fn has_credential(&self, cred_id: &CredentialID) -> bool {
+        self.passkeys.iter().any(|pk| pk.passkey.cred_id() == cred_id)
+    }
+}
+
+/// Utilisateur tel qu'enregistré, y compris dans l'ancien format à une seule passkey
+#[derive(Deserialize)]
+struct StoredUser {
+    first_name: String,
+    last_name: String,
+    email: String,
+    #[serde(default)]
+    passkeys: Vec<UserPasskey>,
+    #[serde(default)]
+    passkey: Option<Passkey>, // Ancien format, avant les passkeys multiples
+    verified: bool,
+    stash: Vec<String>,
+    #[serde(default)]
+    user_handle: Option<Uuid>,
+}
+
+impl From<StoredUser> for User {
+    fn from(stored: StoredUser) -> Self {
+        let mut passkeys = stored.passkeys;
+        if let Some(passkey) = stored.passkey {
+            if !passkeys.iter().any(|pk| pk.passkey.cred_id() == passkey.cred_id()) {
+                passkeys.push(UserPasskey {
+                    name: DEFAULT_PASSKEY_NAME.to_string(),
+                    passkey,
+                });
+            }
+        }
+
+        Self {
+            first_name: stored.first_name,
+            last_name: stored.last_name,
+            email: stored.email,
+            passkeys,
+            verified: stored.verified,
+            stash: stored.stash,
+            user_handle: stored.user_handle,
+        }
+    }
+}
+
+pub fn create(email: &str, first_name: &str, last_name: &str, user_handle: Uuid) -> Result<bool> {
+    let user = User {
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        email: email.to_string(),
+        passkeys: Vec::new(),
+        user_handle: Some(user_handle),
+        verified: false,
+        stash: Vec::new(),
+    };
+
+    storage().insert_user(&user)
+}
+
+/// Modifie un utilisateur existant
+fn update(email: &str, mut update: impl FnMut(&mut User) -> Result<()>) -> Result<()> {
+    if !storage().update_user(email, &mut update)? {
+        bail!("User not found");
+    }
+    Ok(())
+}
+
+/// Ajoute une passkey à la liste de celles de l'utilisateur
+pub fn add_passkey(email: &str, name: &str, passkey: Passkey) -> Result<()> {
+    update(email, |user| {
+        if user.passkeys.iter().any(|pk| pk.passkey.cred_id() == passkey.cred_id()) {
+            bail!("Passkey already registered");
+        }
+
+        user.passkeys.push(UserPasskey {
+            name: name.to_string(),
+            passkey: passkey.clone(),
+        });
+        Ok(())
+    })
+}
+
+/// Remplace toutes les passkeys de l'utilisateur par une unique nouvelle passkey
+pub fn reset_passkeys(email: &str, name: &str, passkey: Passkey) -> Result<()> {
+    update(email, |user| {
+        user.passkeys = vec![UserPasskey {
+            name: name.to_string(),
+            passkey: passkey.clone(),
+        }];
+        Ok(())
+    })
+}
+
+pub fn get_passkeys(email: &str) -> Result<Vec<Passkey>> {
+    let user = storage().get_user(email)?.ok_or_else(|| anyhow!("User not found"))?;
+    Ok(user.passkeys.into_iter().map(|pk| pk.passkey).collect())
+}
+
+/// Renomme une passkey de l'utilisateur
+pub fn rename_passkey(email: &str, cred_id: &CredentialID, name: &str) -> Result<()> {
+    update(email, |user| {
+        let passkey = user
+            .passkeys
+            .iter_mut()
+            .find(|pk| pk.passkey.cred_id() == cred_id)
+            .ok_or_else(|| anyhow!("Passkey not found"))?;
+
+        passkey.name = name.to_string();
+        Ok(())
+    })
+}
+
+/// Révoque une passkey de l'utilisateur, sans permettre de retirer la dernière
+pub fn remove_passkey(email: &str, cred_id: &CredentialID) -> Result<()> {
+    update(email, |user| {
+        let index = user
+            .passkeys
+            .iter()
+            .position(|pk| pk.passkey.cred_id() == cred_id)
+            .ok_or_else(|| anyhow!("Passkey not found"))?;
+        if user.passkeys.len() == 1 {
+            bail!("Cannot revoke the last passkey");
+        }
+
+        user.passkeys.remove(index);
+        Ok(())
+    })
+}
+
+/// Met à jour le compteur de la passkey utilisée lors d'une authentification réussie
+pub fn update_passkey_credential(email: &str, result: &AuthenticationResult) -> Result<()> {
+    update(email, |user| {
+        for pk in user.passkeys.iter_mut() {
+            pk.passkey.update_credential(result);
+        }
+        Ok(())
+    })
+}
+
+/// Retourne l'identifiant WebAuthn de l'utilisateur, en lui en attribuant un s'il n'en a pas encore
+pub fn user_handle(email: &str) -> Result<Uuid> {
+    let mut handle = Uuid::new_v4();
+    update(email, |user| {
+        handle = *user.user_handle.get_or_insert(handle);
+        Ok(())
+    })?;
+    Ok(handle)
+}
+
+/// Retrouve l'utilisateur possédant l'identifiant WebAuthn donné
+pub fn find_by_user_handle(handle: &Uuid) -> Option<User> {
+    storage().user_by_handle(handle).ok()?
+}
+
+pub fn get(email: &str) -> Option<User> {
+    storage().get_user(email).ok()?
+}
+
+pub fn exists(email: &str) -> Result<bool> {
+    Ok(storage().get_user(email)?.is_some())
+}
+
+pub fn verify(email: &str) -> Result<()> {
+    update(email, |user| {
+        user.verified = true;
+        Ok(())
+    })
+}
@@ -0,0 +1,414 @@
+//! Stockage dans des fichiers YAML, un fichier par type de données.
+//! Les données sont conservées en mémoire et chaque modification réécrit le fichier correspondant.
+//! Les sessions, enregistrées à chaque requête, ont chacune leur fichier et sont lues depuis le disque.
+
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use uuid::Uuid;
+use crate::consts;
+use super::{email::Email, post::Post, storage::Storage, token::Token, user::User};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct EmailsDb {
+    pub next_pk: u64,
+    pub emails: HashMap<u64, Email>,
+}
+
+/// Contenu d'un fichier YAML
+struct Table<T> {
+    path: PathBuf,
+    data: RwLock<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default + Clone> Table<T> {
+    fn open(path: PathBuf) -> Result<Self> {
+        let data = load(&path)?;
+        Ok(Self {
+            path,
+            data: RwLock::new(data),
+        })
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R> {
+        let data = self.data.read().or(Err(anyhow!("DB poisoned")))?;
+        Ok(f(&data))
+    }
+
+    /// Applique une modification sur une copie des données, qui ne remplace
+    /// les données courantes qu'une fois la modification réussie et enregistrée
+    fn modify<R>(&self, f: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+        let mut data = self.data.write().or(Err(anyhow!("DB poisoned")))?;
+        let mut copy = data.clone();
+        let result = f(&mut copy)?;
+        save(&copy, &self.path, true)?;
+        *data = copy;
+        Ok(result)
+    }
+}
+
+/// Stockage dans les fichiers YAML du dossier de données
+pub struct YamlStorage {
+    users: Table<HashMap<String, User>>,
+    tokens: Table<HashMap<String, Token>>,
+    emails: Table<EmailsDb>,
+    posts: Table<Vec<Post>>,
+    sessions: PathBuf,        // Dossier des sessions, un fichier par session
+    sessions_lock: Mutex<()>, // Sérialise les écritures de sessions, qui partagent leurs fichiers temporaires
+}
+
+impl YamlStorage {
+    /// Charge les fichiers du dossier donné, un fichier absent correspond à une base vide
+    pub fn open(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            users: Table::open(dir.join(consts::USERS_DB_FILE))?,
+            tokens: Table::open(dir.join(consts::TOKENS_DB_FILE))?,
+            emails: Table::open(dir.join(consts::EMAILS_DB_FILE))?,
+            posts: Table::open(dir.join(consts::POSTS_DB_FILE))?,
+            sessions: dir.join(consts::SESSIONS_DIR),
+            sessions_lock: Mutex::new(()),
+        })
+    }
+
+    /// Fichier d'une session, l'identifiant ne pouvant pas sortir du dossier des sessions
+    fn session_path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            bail!("Invalid session id");
+        }
+        Ok(self.sessions.join(format!("{}.yaml", id)))
+    }
+
+    /// Charge une session, un fichier illisible étant traité comme une session absente
+    fn read_session(path: &Path) -> Option<Session> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_yaml::from_str(&content).ok()
+    }
+}
+
+impl Storage for YamlStorage {
+    fn insert_user(&self, user: &User) -> Result<bool> {
+        self.users.modify(|users| {
+            if users.contains_key(&user.email) {
+                return Ok(false);
+            }
+
+            users.insert(user.email.clone(), user.clone());
+            Ok(true)
+        })
+    }
+
+    fn get_user(&self, email: &str) -> Result<Option<User>> {
+        self.users.read(|users| users.get(email).cloned())
+    }
+
+    fn users(&self) -> Result<Vec<User>> {
+        self.users.read(|users| users.values().cloned().collect())
+    }
+
+    fn update_user(&self, email: &str, update: &mut dyn FnMut(&mut User) -> Result<()>) -> Result<bool> {
+        self.users.modify(|users| match users.get_mut(email) {
+            Some(user) => update(user).map(|_| true),
+            None => Ok(false),
+        })
+    }
+
+    fn insert_token(&self, token: &str, entry: &Token) -> Result<()> {
+        self.tokens.modify(|tokens| {
+            tokens.insert(token.to_string(), entry.clone());
+            Ok(())
+        })
+    }
+
+    fn take_token(&self, token: &str, predicate: &dyn Fn(&Token) -> bool) -> Result<Option<Token>> {
+        if !self.tokens.read(|tokens| tokens.get(token).is_some_and(predicate))? {
+            return Ok(None);
+        }
+
+        self.tokens.modify(|tokens| {
+            Ok(tokens
+                .remove(token)
+                .filter(|entry| predicate(entry)))
+        })
+    }
+
+    fn tokens(&self) -> Result<Vec<(String, Token)>> {
+        self.tokens.read(|tokens| tokens.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+
+    fn retain_tokens(&self, keep: &dyn Fn(&Token) -> bool) -> Result<usize> {
+        if self.tokens.read(|tokens| tokens.values().all(keep))? {
+            return Ok(0);
+        }
+
+        self.tokens.modify(|tokens| {
+            let count = tokens.len();
+            tokens.retain(|_, entry| keep(entry));
+            Ok(count - tokens.len())
+        })
+    }
+
+    fn insert_email(&self, email: &Email) -> Result<u64> {
+        self.emails.modify(|db| {
+            let pk = db.next_pk;
+            db.next_pk += 1;
+            db.emails.insert(pk, Email { pk, ..email.clone() });
+            Ok(pk)
+        })
+    }
+
+    fn get_email(&self, pk: u64) -> Result<Option<Email>> {
+        self.emails.read(|db| db.emails.get(&pk).cloned())
+    }
+
+    fn emails(&self) -> Result<Vec<Email>> {
+        self.emails.read(|db| db.emails.values().cloned().collect())
+    }
+
+    fn delete_email(&self, pk: u64) -> Result<bool> {
+        if !self.emails.read(|db| db.emails.contains_key(&pk))? {
+            return Ok(false);
+        }
+
+        self.emails.modify(|db| Ok(db.emails.remove(&pk).is_some()))
+    }
+
+    fn insert_post(&self, post: &Post) -> Result<()> {
+        self.posts.modify(|posts| {
+            posts.push(post.clone());
+            Ok(())
+        })
+    }
+
+    fn get_post(&self, id: &Uuid) -> Result<Option<Post>> {
+        self.posts.read(|posts| posts.iter().find(|post| post.id == *id).cloned())
+    }
+
+    fn posts(&self) -> Result<Vec<Post>> {
+        self.posts.read(|posts| posts.clone())
+    }
+
+    fn update_post(&self, id: &Uuid, update: &mut dyn FnMut(&mut Post) -> Result<()>) -> Result<bool> {
+        self.posts.modify(|posts| match posts.iter_mut().find(|post| post.id == *id) {
+            Some(post) => update(post).map(|_| true),
+            None => Ok(false),
+        })
+    }
+
+    fn delete_post(&self, id: &Uuid) -> Result<bool> {
+        if self.get_post(id)?.is_none() {
+            return Ok(false);
+        }
+
+        self.posts.modify(|posts| {
+            let count = posts.len();
+            posts.retain(|post| post.id != *id);
+            Ok(posts.len() != count)
+        })
+    }
+
+    fn save_session(&self, session: &Session) -> Result<()> {
+        let path = self.session_path(&session.id().to_string())?;
+        let _lock = self.sessions_lock.lock().or(Err(anyhow!("DB poisoned")))?;
+        save(session, &path, false)
+    }
+
+    fn get_session(&self, id: &str, now: OffsetDateTime) -> Result<Option<Session>> {
+        let path = self.session_path(id)?;
+        Ok(Self::read_session(&path).filter(|session| session.expiry_date() > now))
+    }
+
+    fn delete_session(&self, id: &str) -> Result<()> {
+        let path = self.session_path(id)?;
+        let _lock = self.sessions_lock.lock().or(Err(anyhow!("DB poisoned")))?;
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete_expired_sessions(&self, now: OffsetDateTime) -> Result<usize> {
+        if !self.sessions.exists() {
+            return Ok(0);
+        }
+
+        let _lock = self.sessions_lock.lock().or(Err(anyhow!("DB poisoned")))?;
+        let mut count = 0;
+        for entry in fs::read_dir(&self.sessions)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "yaml") {
+                continue;
+            }
+            if Self::read_session(&path).is_none_or(|session| session.expiry_date() <= now) {
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Enregistre des données dans un fichier YAML sans jamais laisser le fichier dans un état partiel :
+/// les données sont écrites dans un fichier temporaire synchronisé sur le disque, qui remplace ensuite
+/// le fichier par un renommage atomique. Les versions précédentes sont conservées dans `<fichier>.1`,
+/// `<fichier>.2`, etc., la plus récente en premier, si `keep_backups` est demandé.
+fn save<T: Serialize>(db: &T, path: &Path, keep_backups: bool) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    create_dir_all(dir).or(Err(anyhow!("Failed to create directory")))?;
+
+    let tmp_path = sibling(path, "tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    serde_yaml::to_writer(&mut writer, db).or(Err(anyhow!("Failed to serialize DB")))?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    if keep_backups {
+        rotate_backups(path)?;
+    }
+    fs::rename(&tmp_path, path)?;
+
+    // Synchronise le dossier pour que le renommage survive à un crash
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Décale les sauvegardes existantes et copie le fichier courant dans `<fichier>.1`
+fn rotate_backups(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for index in (1..consts::YAML_BACKUP_COUNT).rev() {
+        let backup = sibling(path, &index.to_string());
+        if backup.exists() {
+            fs::rename(&backup, sibling(path, &(index + 1).to_string()))?;
+        }
+    }
+    fs::copy(path, sibling(path, "1")).context("Failed to back up DB")?;
+    Ok(())
+}
+
+/// Chemin `<fichier>.<suffix>` à côté du fichier donné
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Charge des données depuis un fichier YAML, un fichier absent correspond à une base vide.
+/// Un fichier illisible ou corrompu est une erreur : il ne doit pas être remplacé par une base vide.
+pub(super) fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    // Un fichier vide ne peut provenir que d'une écriture interrompue, `save` écrit toujours un document
+    let parsed = if content.trim().is_empty() {
+        Err(anyhow!("empty file"))
+    } else {
+        serde_yaml::from_str(&content).map_err(anyhow::Error::from)
+    };
+
+    parsed.with_context(|| {
+        let backups = (1..=consts::YAML_BACKUP_COUNT)
+            .map(|index| sibling(path, &index.to_string()))
+            .filter(|backup| backup.exists())
+            .map(|backup| backup.display().to_string())
+            .collect::<Vec<_>>();
+
+        if backups.is_empty() {
+            format!("{} is corrupted and no backup is available", path.display())
+        } else {
+            format!("{} is corrupted, restore it from a backup ({})", path.display(), backups.join(", "))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_sessions::Expiry;
+
+    fn test_dir() -> PathBuf {
+        let dir = Path::new("./target/test-data/yaml").join(Uuid::new_v4().to_string());
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_save_keeps_rotating_backups() {
+        let path = test_dir().join("db.yaml");
+        for value in 0..=consts::YAML_BACKUP_COUNT + 1 {
+            save(&value, &path, true).unwrap();
+        }
+
+        let last = consts::YAML_BACKUP_COUNT + 1;
+        assert_eq!(load::<usize>(&path).unwrap(), last);
+        for index in 1..=consts::YAML_BACKUP_COUNT {
+            assert_eq!(load::<usize>(&sibling(&path, &index.to_string())).unwrap(), last - index);
+        }
+        assert!(!sibling(&path, &(consts::YAML_BACKUP_COUNT + 1).to_string()).exists());
+        assert!(!sibling(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn test_sessions_are_stored_one_file_each() {
+        let dir = test_dir();
+        let storage = YamlStorage::open(&dir).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let active = Session::new(Some(Expiry::AtDateTime(now + time::Duration::hours(1))));
+        let expired = Session::new(Some(Expiry::AtDateTime(now - time::Duration::minutes(1))));
+        active.insert("visits", 1).unwrap();
+        storage.save_session(&active).unwrap();
+        active.insert("visits", 2).unwrap();
+        storage.save_session(&active).unwrap();
+        storage.save_session(&expired).unwrap();
+        assert_eq!(fs::read_dir(dir.join(consts::SESSIONS_DIR)).unwrap().count(), 2);
+
+        let id = active.id().to_string();
+        let loaded = storage.get_session(&id, now).unwrap().unwrap();
+        assert_eq!(loaded.get::<u32>("visits").unwrap(), Some(2));
+        assert!(storage.get_session(&expired.id().to_string(), now).unwrap().is_none());
+        assert!(storage.get_session("../users", now).is_err());
+
+        assert_eq!(storage.delete_expired_sessions(now).unwrap(), 1);
+        storage.delete_session(&id).unwrap();
+        storage.delete_session(&id).unwrap();
+        assert!(storage.get_session(&id, now).unwrap().is_none());
+        assert_eq!(fs::read_dir(dir.join(consts::SESSIONS_DIR)).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_corrupted_file_is_reported() {
+        let dir = test_dir();
+        let path = dir.join("db.yaml");
+        save(&vec![1, 2, 3], &path, true).unwrap();
+        save(&vec![1, 2, 3, 4], &path, true).unwrap();
+        fs::write(&path, "- 1\n- [2\n").unwrap();
+
+        let error = format!("{:#}", load::<Vec<u32>>(&path).unwrap_err());
+        assert!(error.contains("is corrupted"));
+        assert!(error.contains("db.yaml.1"));
+
+        // Truncated files must not be read as an empty database either
+        fs::write(&path, "").unwrap();
+        assert!(load::<Vec<u32>>(&path).is_err());
+        assert!(YamlStorage::open(&dir).is_ok());
+        assert_eq!(load::<Vec<u32>>(&dir.join("missing.yaml")).unwrap(), Vec::<u32>::new());
+    }
+}
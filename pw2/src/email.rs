@@ -1,12 +1,255 @@
 //! Gestion des fonctionnalités liées aux emails, telles que l'envoi et la création de liens de vérification.
+//! Les emails sont transmis par un `Mailer`, choisi via la variable d'environnement `MAIL_TRANSPORT` :
+//! `outbox` (par défaut) les enregistre dans la base de données simulée, `smtp` les envoie à un serveur SMTP.
+//! Un transport inconnu ou une configuration SMTP invalide empêche le démarrage du serveur.
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
 use log::info;
-use crate::database;
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use std::env;
+use crate::{config, database, HBS};
 
-/// Envoie un email simulé en ajoutant ses détails à la base de données.
-pub fn send_mail(to: &str, subject: &str, body: &str) -> Result<()> {
+/// Email prêt à être envoyé, avec une version texte et une version HTML
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+}
+
+/// Moyen de transport des emails
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: &Message) -> Result<()>;
+}
+
+/// Transport simulé, ajoute les emails à la base de données
+pub struct OutboxMailer;
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, message: &Message) -> Result<()> {
+        database::email::add(&message.to, &message.subject, &message.text_body, Some(&message.html_body))?;
+        Ok(())
+    }
+}
+
+/// Transport envoyant les emails à un serveur SMTP
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Construit le transport depuis les variables d'environnement :
+    /// `SMTP_HOST`, `SMTP_PORT`, `SMTP_TLS` (`none`, `starttls` ou `tls`), `SMTP_USERNAME`,
+    /// `SMTP_PASSWORD` et `SMTP_FROM`.
+    pub fn from_env() -> Result<Self> {
+        let host = env::var("SMTP_HOST").context("SMTP_HOST is required")?;
+        let from = env::var("SMTP_FROM")
+            .context("SMTP_FROM is required")?
+            .parse::<Mailbox>()
+            .context("SMTP_FROM is not a valid address")?;
+
+        let tls_parameters = TlsParameters::new(host.clone()).context("Invalid SMTP TLS parameters");
+        let (tls, default_port) = match env::var("SMTP_TLS").as_deref().unwrap_or("starttls") {
+            "none" => (Tls::None, 25),
+            "starttls" => (Tls::Required(tls_parameters?), 587),
+            "tls" => (Tls::Wrapper(tls_parameters?), 465),
+            other => return Err(anyhow!("Unknown SMTP_TLS mode '{}'", other)),
+        };
+        let port = match env::var("SMTP_PORT") {
+            Ok(port) => port.parse().context("SMTP_PORT is not a valid port")?,
+            Err(_) => default_port,
+        };
+
+        let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            (Ok(username), Ok(password)) => Some(Credentials::new(username, password)),
+            _ => None,
+        };
+
+        Ok(Self::new(&host, port, tls, credentials, from))
+    }
+
+    pub fn new(host: &str, port: u16, tls: Tls, credentials: Option<Credentials>, from: Mailbox) -> Self {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(port)
+            .tls(tls);
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+
+        Self {
+            transport: builder.build(),
+            from,
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: &Message) -> Result<()> {
+        let email = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(message.to.parse().context("Invalid recipient address")?)
+            .subject(&message.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.clone(),
+                message.html_body.clone(),
+            ))
+            .context("Failed to build email")?;
+
+        self.transport.send(email).await.context("Failed to send email")?;
+        Ok(())
+    }
+}
+
+/// Transport configuré par l'environnement, choisi au démarrage
+static MAILER: OnceCell<Box<dyn Mailer>> = OnceCell::new();
+
+/// Construit le transport demandé, une configuration invalide est une erreur
+fn select_mailer(transport: Option<&str>) -> Result<Box<dyn Mailer>> {
+    match transport {
+        None | Some("outbox") => Ok(Box::new(OutboxMailer)),
+        Some("smtp") => Ok(Box::new(SmtpMailer::from_env().context("Invalid SMTP configuration")?)),
+        Some(other) => Err(anyhow!("Unknown MAIL_TRANSPORT '{}'", other)),
+    }
+}
+
+/// Initialise le transport depuis `MAIL_TRANSPORT`, une seule fois au démarrage
+pub fn init() -> Result<()> {
+    let mailer = select_mailer(env::var("MAIL_TRANSPORT").ok().as_deref())?;
+    MAILER.set(mailer).map_err(|_| anyhow!("Mailer already initialized"))
+}
+
+/// Retourne le transport configuré
+fn mailer() -> &'static dyn Mailer {
+    #[cfg(test)]
+    return MAILER.get_or_init(|| Box::new(OutboxMailer)).as_ref();
+
+    #[cfg(not(test))]
+    MAILER.get().expect("Mailer not initialized").as_ref()
+}
+
+/// Envoie un email avec le transport configuré.
+pub async fn send_mail(message: &Message) -> Result<()> {
     info!("Sending an email");
-    database::email::add(to, subject, body)?;
-    Ok(())
-}
\ No newline at end of file
+    mailer().send(message).await
+}
+
+/// Construit un email à partir des templates texte et HTML `emails/<template>`
+fn render(template: &str, to: &str, subject: &str, data: &serde_json::Value) -> Result<Message> {
+    Ok(Message {
+        to: to.to_string(),
+        subject: subject.to_string(),
+        text_body: HBS.render(&format!("emails/{}.txt", template), data)?,
+        html_body: HBS.render(&format!("emails/{}.html", template), data)?,
+    })
+}
+
+/// Envoie l'email contenant le lien de validation d'un nouveau compte
+pub async fn send_verification_mail(to: &str, first_name: &str, token: &str) -> Result<()> {
+    let link = config::get().public_url(&format!("/validate/{}", token));
+    let message = render(
+        "verification",
+        to,
+        "Verify your account",
+        &json!({ "first_name": first_name, "link": link }),
+    )?;
+    send_mail(&message).await
+}
+
+/// Envoie l'email contenant le lien de récupération d'un compte
+pub async fn send_recovery_mail(to: &str, token: &str) -> Result<()> {
+    let link = config::get().public_url(&format!("/recover/{}", token));
+    let message = render("recovery", to, "Account Recovery", &json!({ "link": link }))?;
+    send_mail(&message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Serveur SMTP minimal acceptant une connexion et retournant les données du message reçu
+    async fn smtp_sink(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut data = String::new();
+        let mut in_data = false;
+
+        writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+
+            let command = line.to_ascii_uppercase();
+            if command.starts_with("EHLO") || command.starts_with("HELO") {
+                writer.write_all(b"250 sink\r\n").await.unwrap();
+            } else if command.starts_with("DATA") {
+                in_data = true;
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await.unwrap();
+            } else if command.starts_with("QUIT") {
+                writer.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                writer.write_all(b"250 OK\r\n").await.unwrap();
+            }
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn test_smtp_mailer_delivers_to_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = tokio::spawn(smtp_sink(listener));
+
+        let from = "lab02 <noreply@example.com>".parse().unwrap();
+        let mailer = SmtpMailer::new("127.0.0.1", port, Tls::None, None, from);
+        let data = json!({ "link": "http://localhost:8080/recover/abc" });
+        let message = render("recovery", "user@example.com", "Account Recovery", &data).unwrap();
+        mailer.send(&message).await.unwrap();
+        drop(mailer);
+
+        let data = sink.await.unwrap();
+        assert!(data.contains("To: user@example.com"));
+        assert!(data.contains("Subject: Account Recovery"));
+        assert!(data.contains("Content-Type: text/plain"));
+        assert!(data.contains("Content-Type: text/html"));
+        assert!(data.contains("http://localhost:8080/recover/abc"));
+    }
+
+    #[test]
+    fn test_unknown_transport_is_rejected() {
+        assert!(select_mailer(None).is_ok());
+        assert!(select_mailer(Some("outbox")).is_ok());
+        assert!(select_mailer(Some("SMTP ")).is_err());
+    }
+
+    #[test]
+    fn test_templates_render_link() {
+        let data = json!({ "first_name": "Jane", "link": "http://localhost:8080/validate/abc" });
+        let message = render("verification", "user@example.com", "Verify your account", &data).unwrap();
+        assert!(message.text_body.contains("Welcome Jane!"));
+        assert!(message.text_body.contains("http://localhost:8080/validate/abc"));
+        assert!(message.html_body.contains("href=\"http://localhost:8080/validate/abc\""));
+    }
+}
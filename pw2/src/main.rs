@@ -1,22 +1,26 @@
 //! Point d'entrée principal de l'application.
 //! Initialise les bases de données, configure Handlebars pour le rendu des templates,
 //! et démarre le serveur web avec Axum.
+//! La commande `import-yaml [dossier]` importe les anciens fichiers YAML dans le stockage configuré,
+//! la commande `gc-uploads` supprime les images qui ne sont plus référencées par aucun post.
 
 mod backend;
 mod database;
 mod utils;
 mod email;
 mod consts;
+mod config;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use axum::Extension;
 use dotenv::dotenv;
 use handlebars::Handlebars;
 use log::info;
 use once_cell::sync::Lazy;
+use tower_sessions::ExpiredDeletion;
 use crate::{
-    consts::HTTP_PORT,
-    backend::handlers_auth::{load_posts_from_file, save_posts_to_file},
+    consts::{CEREMONY_SWEEP_INTERVAL_SECS, TOKEN_SWEEP_INTERVAL_SECS},
+    backend::session_store::SessionBackend,
 };
 
 // Initialisation de Handlebars pour le rendu des templates
@@ -35,36 +39,107 @@ async fn main() {
         .filter_level(log::LevelFilter::Info)
         .init();
 
-    // Charger les données des posts
-    if let Err(e) = load_posts_from_file() {
-        eprintln!("Erreur lors du chargement des posts: {}", e);
+    // Charger et valider la configuration, le serveur ne démarre pas si elle est invalide
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Configuration invalide: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    config::init(config).expect("Configuration already initialized");
+    let config = config::get();
+
+    // Le transport des emails est validé au démarrage, sans repli silencieux sur la boîte simulée
+    if let Err(e) = email::init() {
+        eprintln!("Configuration des emails invalide: {:#}", e);
+        std::process::exit(1);
     }
 
-    // Charger les autres bases de données
-    database::user::load().ok();
-    database::email::load().ok();
+    // Ouvrir le stockage des données
+    if let Err(e) = database::init() {
+        eprintln!("Erreur lors de l'ouverture du stockage: {:#}", e);
+        std::process::exit(1);
+    }
 
-    // Configurer Handlebars comme extension pour le routeur
-    let hbs = Arc::new(HBS.clone());
-    let app = backend::router::get_router().layer(Extension(hbs));
+    // Commandes ponctuelles
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("import-yaml") => {
+            let source = args.get(1).map(PathBuf::from).unwrap_or_else(|| config.data_dir.clone());
+            match database::import::import_yaml(&source, database::storage()) {
+                Ok(report) => info!("Imported {:?} from {}", report, source.display()),
+                Err(e) => {
+                    eprintln!("Erreur lors de l'import: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("gc-uploads") => {
+            match database::upload::collect_garbage() {
+                Ok(count) => info!("Removed {} unreferenced uploads", count),
+                Err(e) => {
+                    eprintln!("Erreur lors du nettoyage des uploads: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(command) => {
+            eprintln!("Commande inconnue: {}", command);
+            std::process::exit(2);
+        }
+        None => (),
+    }
 
-    // Ajouter une gestion de fin pour sauvegarder les posts
+    // Supprimer régulièrement les tokens expirés
     tokio::spawn(async {
-        tokio::signal::ctrl_c().await.unwrap();
-        if let Err(e) = save_posts_to_file() {
-            eprintln!("Erreur lors de la sauvegarde des posts: {}", e);
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(TOKEN_SWEEP_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            match database::token::sweep_expired() {
+                Ok(0) => (),
+                Ok(count) => info!("Removed {} expired tokens", count),
+                Err(e) => eprintln!("Erreur lors de la suppression des tokens expirés: {}", e),
+            }
         }
     });
 
+    // Supprimer régulièrement les cérémonies WebAuthn abandonnées
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(CEREMONY_SWEEP_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let count = backend::handlers_unauth::sweep_expired_ceremonies();
+            if count > 0 {
+                info!("Removed {} expired WebAuthn ceremonies", count);
+            }
+        }
+    });
+
+    // Sélectionner le store de sessions et supprimer régulièrement les sessions expirées
+    let session_store = SessionBackend::from_config(&config.session);
+    tokio::spawn(
+        session_store
+            .clone()
+            .continuously_delete_expired(tokio::time::Duration::from_secs(config.session.sweep_interval_secs)),
+    );
+
+    // Configurer Handlebars comme extension pour le routeur
+    let hbs = Arc::new(HBS.clone());
+    let app = backend::router::get_router(session_store).layer(Extension(hbs));
+
     // Démarrer le serveur web
-    let addr = SocketAddr::from(([0, 0, 0, 0], HTTP_PORT));
-    info!("Listening on {}", addr);
+    let addr = config.bind_address;
+    info!("Listening on {} (public URL: {})", addr, config.public_base_url);
 
     let listener = tokio::net::TcpListener::bind(addr)
         .await
         .expect("Failed to open web server listener");
 
-    axum::serve(listener, app)
+    // L'adresse du client est utilisée pour limiter le nombre de requêtes
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .expect("Failed to bind Axum to listener");
 }
@@ -1,4 +1,5 @@
 //! Modules utilitaires pour diverses fonctionnalités.
 
 pub(crate) mod input;
+pub(crate) mod markdown;
 pub(crate) mod webauthn;
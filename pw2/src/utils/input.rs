@@ -1,6 +1,9 @@
 use ammonia::is_html;
-use anyhow::{bail, Result};
-use image::ImageFormat;
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::imageops::FilterType;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits};
+use std::fmt;
+use std::io::Cursor;
 use std::path::Path;
 use validator::{ValidateEmail, ValidateNonControlCharacter};
 
@@ -42,41 +45,219 @@ impl AsRef<str> for UserEmail {
     }
 }
 
-/// Validates an uploaded image file
+/// Image formats accepted for uploads
+const ALLOWED_IMAGE_FORMATS: [ImageFormat; 4] = [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP, ImageFormat::Gif];
+
+/// Limits enforced on uploaded images, before and while decoding them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLimits {
+    pub max_file_bytes: usize,
+    pub max_dimension: u32,
+    pub max_pixels: u64,
+    pub max_decoder_alloc_bytes: u64,
+}
+
+/// Implementation of `Default` for `ImageLimits`
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 8 * 1024 * 1024,
+            max_dimension: 8_192,
+            max_pixels: 40_000_000,
+            max_decoder_alloc_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Reason why an uploaded image was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageRejection {
+    FileTooLarge { limit: usize },
+    UnsupportedExtension,
+    FormatMismatch,
+    InvalidImage,
+    Animated,
+    DimensionsTooLarge { width: u32, height: u32, limit: u32 },
+    TooManyPixels { pixels: u64, limit: u64 },
+    DecoderLimitExceeded,
+    EncodingFailed,
+}
+
+/// Implementation of `Display` for `ImageRejection`
+///
+/// The messages are meant to be shown to the uploader
+impl fmt::Display for ImageRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileTooLarge { limit } => write!(f, "Image file exceeds {} bytes", limit),
+            Self::UnsupportedExtension => write!(f, "File must have a .jpg, .jpeg, .png, .webp or .gif extension"),
+            Self::FormatMismatch => write!(f, "File extension does not match the image format"),
+            Self::InvalidImage => write!(f, "Invalid image format"),
+            Self::Animated => write!(f, "Animated images are not supported"),
+            Self::DimensionsTooLarge { width, height, limit } => {
+                write!(f, "Image is {}x{}, sides are limited to {} pixels", width, height, limit)
+            }
+            Self::TooManyPixels { pixels, limit } => {
+                write!(f, "Image has {} pixels, the limit is {}", pixels, limit)
+            }
+            Self::DecoderLimitExceeded => write!(f, "Image needs too much memory to be decoded"),
+            Self::EncodingFailed => write!(f, "Failed to re-encode the image"),
+        }
+    }
+}
+
+impl std::error::Error for ImageRejection {}
+
+/// Uploaded image, decoded so that only its pixels are kept when it is re-encoded
+#[derive(Debug, Clone)]
+pub struct SanitizedImage {
+    pub format: ImageFormat,
+    image: DynamicImage,
+}
+
+/// Image re-encoded in the format of the upload
+#[derive(Debug, Clone)]
+pub struct EncodedImage {
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Implementation of `SanitizedImage`
+impl SanitizedImage {
+    /// File extension matching the image format
+    pub fn extension(&self) -> &'static str {
+        self.format.extensions_str()[0]
+    }
+
+    /// Re-encodes the image, downscaled to fit within `max_size` pixels on both sides
+    ///
+    /// Re-encoding drops metadata (EXIF, GPS, comments) and any payload appended to the file.
+    pub fn encode(&self, max_size: u32) -> Result<EncodedImage, ImageRejection> {
+        let image = if self.image.width() > max_size || self.image.height() > max_size {
+            self.image.resize(max_size, max_size, FilterType::Lanczos3)
+        } else {
+            self.image.clone()
+        };
+
+        // Only the pixels are written back, in a color type supported by the encoder
+        let image = match self.format {
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+            ImageFormat::WebP if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+            ImageFormat::WebP => DynamicImage::ImageRgb8(image.to_rgb8()),
+            _ => image,
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        image
+            .write_to(&mut encoded, self.format)
+            .map_err(|_| ImageRejection::EncodingFailed)?;
+
+        Ok(EncodedImage {
+            bytes: encoded.into_inner(),
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+}
+
+/// Validates an uploaded image file and decodes it
+///
+/// The dimensions are read from the image header and checked before anything is decoded,
+/// the decoder itself is bounded by `limits.max_decoder_alloc_bytes`.
 ///
 /// # Arguments
 /// * `bytes` - The raw bytes of the uploaded file
 /// * `filename` - The original filename to check extension
+/// * `limits` - The limits the image must fit in
 ///
 /// # Returns
-/// * `Ok(())` if validation passes
-/// * `Err` with message if validation fails
-pub fn validate_image(bytes: &[u8], filename: &str) -> Result<()> {
+/// * `Ok(SanitizedImage)` if validation passes
+/// * `Err(ImageRejection)` with the reason if validation fails
+pub fn validate_image(bytes: &[u8], filename: &str, limits: &ImageLimits) -> Result<SanitizedImage, ImageRejection> {
+    if bytes.len() > limits.max_file_bytes {
+        return Err(ImageRejection::FileTooLarge {
+            limit: limits.max_file_bytes,
+        });
+    }
+
     // Check file extension
-    match {
-        Path::new(filename)
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("")
-    } {
-        "jpg" | "jpeg" => (),
-        _ => bail!("File must have a .jpg or .jpeg extension"),
+    let extension_format = Path::new(filename)
+        .extension()
+        .and_then(ImageFormat::from_extension)
+        .filter(|format| ALLOWED_IMAGE_FORMATS.contains(format))
+        .ok_or(ImageRejection::UnsupportedExtension)?;
+
+    // Validate image format using image crate, the extension must match the contents
+    let format = match image::guess_format(bytes) {
+        Ok(format) if format == extension_format => format,
+        Ok(_) => return Err(ImageRejection::FormatMismatch),
+        Err(_) => return Err(ImageRejection::InvalidImage),
+    };
+
+    // Check the dimensions announced by the header
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_dimensions()
+        .map_err(|_| ImageRejection::InvalidImage)?;
+    if width > limits.max_dimension || height > limits.max_dimension {
+        return Err(ImageRejection::DimensionsTooLarge {
+            width,
+            height,
+            limit: limits.max_dimension,
+        });
+    }
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > limits.max_pixels {
+        return Err(ImageRejection::TooManyPixels {
+            pixels,
+            limit: limits.max_pixels,
+        });
     }
 
-    // Validate image format using image crate
-    match image::guess_format(bytes) {
-        Ok(format) if format == ImageFormat::Jpeg => (),
-        Ok(_) => bail!("File must be a valid JPEG image"),
-        Err(_) => bail!("Invalid image format"),
+    if is_animated(bytes, format, decoder_limits(limits))? {
+        return Err(ImageRejection::Animated);
     }
 
     // Validate the image contents
-    match image::load_from_memory_with_format(bytes, ImageFormat::Jpeg) {
-        Ok(_) => Ok(()),
-        Err(_) => bail!("Invalid image format"),
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(decoder_limits(limits));
+    let image = reader.decode().map_err(decoding_rejection)?;
+
+    Ok(SanitizedImage { format, image })
+}
+
+/// Limits given to the decoders
+fn decoder_limits(limits: &ImageLimits) -> Limits {
+    let mut decoder_limits = Limits::default();
+    decoder_limits.max_image_width = Some(limits.max_dimension);
+    decoder_limits.max_image_height = Some(limits.max_dimension);
+    decoder_limits.max_alloc = Some(limits.max_decoder_alloc_bytes);
+    decoder_limits
+}
+
+/// Maps a decoding error to the reason of the rejection
+fn decoding_rejection(error: ImageError) -> ImageRejection {
+    match error {
+        ImageError::Limits(_) => ImageRejection::DecoderLimitExceeded,
+        _ => ImageRejection::InvalidImage,
     }
 }
 
+/// Checks whether an image contains more than one frame
+fn is_animated(bytes: &[u8], format: ImageFormat, limits: Limits) -> Result<bool, ImageRejection> {
+    let animated = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes)).and_then(|mut decoder| {
+            decoder.set_limits(limits)?;
+            let mut frames = decoder.into_frames();
+            Ok(frames.next().transpose()?.is_some() && frames.next().transpose()?.is_some())
+        }),
+        ImageFormat::Png => PngDecoder::new(Cursor::new(bytes)).and_then(|decoder| decoder.is_apng()),
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(bytes)).map(|decoder| decoder.has_animation()),
+        _ => Ok(false),
+    };
+
+    animated.map_err(decoding_rejection)
+}
+
 /// Wrapper around textual content given by an external source
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TextualContent(String);
@@ -86,6 +267,8 @@ impl TextualContent {
     /// Attempts to create a new `TextualContent` instance from a string representing
     /// long-form textual content (e.g. blog post, article)
     ///
+    /// NOTE: line breaks are allowed, and normalized to `\n`
+    ///
     /// # Arguments
     /// * `content` - The raw content to validate
     ///
@@ -93,7 +276,7 @@ impl TextualContent {
     /// * `Some(TextualContent)` if content is valid
     /// * `None` if content is empty or unsafe
     pub fn try_new_long_form_content(content: &str) -> Option<Self> {
-        Self::try_new(content, 2_000)
+        Self::try_new(&content.replace("\r\n", "\n"), 2_000, true)
     }
 
     /// Attempts to create a new `TextualContent` instance from a string representing
@@ -106,17 +289,21 @@ impl TextualContent {
     /// * `Some(TextualContent)` if content is valid
     /// * `None` if content is empty or unsafe
     pub fn try_new_short_form_content(content: &str) -> Option<Self> {
-        Self::try_new(content, 250)
+        Self::try_new(content, 250, false)
     }
 
-    fn try_new(content: &str, max_length: usize) -> Option<Self> {
+    fn try_new(content: &str, max_length: usize, multiline: bool) -> Option<Self> {
         let trimmed = content.trim();
-        if {
-            trimmed.is_empty()
-                || trimmed.len() > max_length
-                || !trimmed.validate_non_control_character()
-                || is_html(trimmed)
-        } {
+        let no_control_character = if multiline {
+            trimmed.split('\n').all(|line| line.validate_non_control_character())
+        } else {
+            trimmed.validate_non_control_character()
+        };
+        if trimmed.is_empty()
+            || trimmed.len() > max_length
+            || !no_control_character
+            || is_html(trimmed)
+        {
             None
         } else {
             Some(Self(trimmed.to_owned()))
@@ -140,24 +327,151 @@ mod tests {
     #[test]
     fn test_validate_image_valid_jpeg() {
         let bytes = include_bytes!("../../tests/test_files/valid.jpg");
-        assert!(validate_image(bytes, "test.jpg").is_ok());
+        assert!(validate_image(bytes, "test.jpg", &ImageLimits::default()).is_ok());
     }
 
     #[test]
     fn test_validate_image_invalid_extension() {
         let bytes = include_bytes!("../../tests/test_files/valid.jpg");
-        assert!(validate_image(bytes, "test.png").is_err());
+        assert!(validate_image(bytes, "test.png", &ImageLimits::default()).is_err());
     }
 
     #[test]
     fn test_validate_image_invalid_format() {
         let bytes = include_bytes!("../../tests/test_files/fake.jpg");
-        assert!(validate_image(bytes, "fake.jpg").is_err());
+        assert!(validate_image(bytes, "fake.jpg", &ImageLimits::default()).is_err());
     }
 
     #[test]
     fn test_validate_image_empty() {
-        assert!(validate_image(&[], "empty.jpg").is_err());
+        assert!(validate_image(&[], "empty.jpg", &ImageLimits::default()).is_err());
+    }
+
+    // Helper function to encode a small image in the given format
+    fn encode_image(format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 10, 10, 255])));
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_validate_image_other_formats() {
+        for (format, filename, extension) in [
+            (ImageFormat::Png, "test.PNG", "png"),
+            (ImageFormat::WebP, "test.webp", "webp"),
+            (ImageFormat::Gif, "test.gif", "gif"),
+        ] {
+            let image = validate_image(&encode_image(format), filename, &ImageLimits::default()).unwrap();
+            assert_eq!(image.format, format);
+            assert_eq!(image.extension(), extension);
+            assert_eq!(image::guess_format(&image.encode(u32::MAX).unwrap().bytes).unwrap(), format);
+        }
+
+        let bytes = include_bytes!("../../tests/test_files/valid.jpg");
+        assert_eq!(validate_image(bytes, "test.jpeg", &ImageLimits::default()).unwrap().extension(), "jpg");
+        assert!(validate_image(&encode_image(ImageFormat::Png), "test.gif", &ImageLimits::default()).is_err());
+        assert!(validate_image(&encode_image(ImageFormat::Bmp), "test.bmp", &ImageLimits::default()).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_strips_trailing_payload() {
+        let payload = b"<?php system($_GET['cmd']); ?>";
+        for (mut bytes, filename) in [
+            (include_bytes!("../../tests/test_files/valid.jpg").to_vec(), "test.jpg"),
+            (encode_image(ImageFormat::Png), "test.png"),
+        ] {
+            bytes.extend_from_slice(payload);
+            let image = validate_image(&bytes, filename, &ImageLimits::default()).unwrap().encode(u32::MAX).unwrap();
+            assert!(!image.bytes.windows(payload.len()).any(|window| window == payload));
+        }
+    }
+
+    #[test]
+    fn test_encode_downscales_keeping_the_aspect_ratio() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::new(400, 100));
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        let image = validate_image(&bytes.into_inner(), "wide.png", &ImageLimits::default()).unwrap();
+
+        let small = image.encode(200).unwrap();
+        assert_eq!((small.width, small.height), (200, 50));
+        let decoded = image::load_from_memory(&small.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (200, 50));
+        let full = image.encode(1000).unwrap();
+        assert_eq!((full.width, full.height), (400, 100));
+    }
+
+    // Helper function to craft a JPEG whose SOF header announces the given dimensions
+    fn jpeg_with_dimensions(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = include_bytes!("../../tests/test_files/valid.jpg").to_vec();
+        // Walk the segments: marker (2 bytes), length (2 bytes), payload
+        let mut offset = 2;
+        while !matches!(bytes[offset + 1], 0xC0..=0xC2) {
+            offset += 2 + u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        }
+        // SOF payload: precision (1 byte), height (2 bytes), width (2 bytes)
+        bytes[offset + 5..offset + 7].copy_from_slice(&height.to_be_bytes());
+        bytes[offset + 7..offset + 9].copy_from_slice(&width.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_validate_image_rejects_oversized_jpeg_headers() {
+        let limits = ImageLimits::default();
+        assert_eq!(
+            validate_image(&jpeg_with_dimensions(60_000, 100), "wide.jpg", &limits).unwrap_err(),
+            ImageRejection::DimensionsTooLarge {
+                width: 60_000,
+                height: 100,
+                limit: 8_192
+            }
+        );
+        assert_eq!(
+            validate_image(&jpeg_with_dimensions(8_000, 8_000), "bomb.jpg", &limits).unwrap_err(),
+            ImageRejection::TooManyPixels {
+                pixels: 64_000_000,
+                limit: 40_000_000
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_image_enforces_byte_and_decoder_limits() {
+        let bytes = jpeg_with_dimensions(4_000, 4_000);
+        let limits = ImageLimits {
+            max_decoder_alloc_bytes: 1024 * 1024,
+            ..ImageLimits::default()
+        };
+        assert_eq!(
+            validate_image(&bytes, "bomb.jpg", &limits).unwrap_err(),
+            ImageRejection::DecoderLimitExceeded
+        );
+
+        let limits = ImageLimits {
+            max_file_bytes: 100,
+            ..ImageLimits::default()
+        };
+        assert_eq!(
+            validate_image(&bytes, "big.jpg", &limits).unwrap_err(),
+            ImageRejection::FileTooLarge { limit: 100 }
+        );
+    }
+
+    #[test]
+    fn test_validate_image_rejects_animated_gif() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut bytes);
+            for color in [0, 255] {
+                let frame = image::RgbaImage::from_pixel(4, 4, image::Rgba([color, color, color, 255]));
+                encoder.encode_frame(image::Frame::new(frame)).unwrap();
+            }
+        }
+        assert_eq!(
+            validate_image(&bytes, "animated.gif", &ImageLimits::default()).unwrap_err(),
+            ImageRejection::Animated
+        );
     }
 
     // Helper function to create test strings of specific lengths
@@ -229,6 +543,14 @@ mod tests {
         assert!(TextualContent::try_new_short_form_content(content_with_escape).is_none());
     }
 
+    #[test]
+    fn test_markup_content() {
+        let content = "**Bold**, _italic_, `code` and [a link](https://example.com/?a=1&b=2)\nNext line";
+        assert!(TextualContent::try_new_long_form_content(content).is_some());
+        assert_eq!(TextualContent::try_new_long_form_content("a\r\nb").unwrap().as_ref(), "a\nb");
+        assert!(TextualContent::try_new_short_form_content("a\nb").is_none());
+    }
+
     #[test]
     fn test_html_content() {
         let html_content = "<p>This is HTML content</p>";
@@ -0,0 +1,186 @@
+//! Mise en forme légère du contenu des posts.
+//! Syntaxe reconnue : `**gras**`, `*italique*` ou `_italique_`, `` `code` ``, `[texte](https://lien)`
+//! et les retours à la ligne. Le HTML produit passe toujours par une liste blanche `ammonia`.
+
+use std::collections::HashSet;
+use ammonia::Builder;
+use once_cell::sync::Lazy;
+
+/// Schémas d'URL autorisés dans les liens
+const LINK_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+// Nettoyeur n'autorisant que les balises produites par le rendu
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::empty();
+    builder
+        .tags(HashSet::from(["strong", "em", "code", "a", "br"]))
+        .tag_attributes([("a", HashSet::from(["href"]))].into())
+        .url_schemes(HashSet::from(LINK_SCHEMES))
+        .link_rel(Some("nofollow noopener"));
+    builder
+});
+
+/// Convertit le contenu d'un post en HTML sûr
+pub fn render(source: &str) -> String {
+    let mut html = String::with_capacity(source.len());
+    render_inline(source, true, &mut html);
+    SANITIZER.clean(&html).to_string()
+}
+
+/// Rendu d'un fragment de texte, `links` indique si des liens peuvent y être ajoutés
+fn render_inline(text: &str, links: bool, out: &mut String) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        let previous = text[..text.len() - rest.len()].chars().next_back();
+
+        match c {
+            // Caractère échappé, affiché tel quel
+            '\\' if after.starts_with(|c: char| "\\`*_[]()".contains(c)) => {
+                let escaped = after.chars().next().unwrap_or_default();
+                escape_char(escaped, out);
+                rest = &after[escaped.len_utf8()..];
+                continue;
+            }
+            '`' => {
+                if let Some(end) = after.find('`').filter(|end| *end > 0) {
+                    out.push_str("<code>");
+                    escape(&after[..end], out);
+                    out.push_str("</code>");
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+            '*' if after.starts_with('*') => {
+                if let Some((inner, remaining)) = delimited(&after[1..], "**") {
+                    out.push_str("<strong>");
+                    render_inline(inner, links, out);
+                    out.push_str("</strong>");
+                    rest = remaining;
+                    continue;
+                }
+            }
+            // `_` n'est reconnu qu'en début de mot, pour ne pas toucher à `snake_case`
+            '*' | '_' if c == '*' || !previous.is_some_and(char::is_alphanumeric) => {
+                let delimiter = if c == '*' { "*" } else { "_" };
+                if let Some((inner, remaining)) = delimited(after, delimiter)
+                    .filter(|(_, remaining)| c == '*' || !remaining.starts_with(char::is_alphanumeric))
+                {
+                    out.push_str("<em>");
+                    render_inline(inner, links, out);
+                    out.push_str("</em>");
+                    rest = remaining;
+                    continue;
+                }
+            }
+            '[' if links => {
+                if let Some((label, url, remaining)) = link(after) {
+                    out.push_str("<a href=\"");
+                    escape(url, out);
+                    out.push_str("\">");
+                    render_inline(label, false, out);
+                    out.push_str("</a>");
+                    rest = remaining;
+                    continue;
+                }
+            }
+            '\n' => {
+                out.push_str("<br>");
+                rest = after;
+                continue;
+            }
+            _ => (),
+        }
+
+        escape_char(c, out);
+        rest = after;
+    }
+}
+
+/// Sépare le texte jusqu'au délimiteur fermant du reste, le contenu ne peut pas être vide
+/// ni commencer ou finir par un espace
+fn delimited<'a>(text: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let end = text.find(delimiter)?;
+    let inner = &text[..end];
+    if inner.is_empty() || inner.starts_with(char::is_whitespace) || inner.ends_with(char::is_whitespace) {
+        return None;
+    }
+    Some((inner, &text[end + delimiter.len()..]))
+}
+
+/// Reconnaît `texte](url)` après un `[`, seuls les schémas autorisés sont acceptés et l'URL ne peut
+/// pas contenir d'espace
+fn link(text: &str) -> Option<(&str, &str, &str)> {
+    let (label, rest) = text.split_once("](")?;
+    let (url, remaining) = rest.split_once(')')?;
+    let scheme = url.split_once(':')?.0;
+    if label.is_empty()
+        || label.contains(['[', '\n'])
+        || url.contains(char::is_whitespace)
+        || !LINK_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str())
+    {
+        return None;
+    }
+    Some((label, url, remaining))
+}
+
+fn escape(text: &str, out: &mut String) {
+    text.chars().for_each(|c| escape_char(c, out));
+}
+
+fn escape_char(c: char, out: &mut String) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&#39;"),
+        _ => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_formatting() {
+        assert_eq!(
+            render("**bold**, *italic*, _also_ and `co*de*`\nnext line"),
+            "<strong>bold</strong>, <em>italic</em>, <em>also</em> and <code>co*de*</code><br>next line"
+        );
+        assert_eq!(render("**bold _and italic_**"), "<strong>bold <em>and italic</em></strong>");
+    }
+
+    #[test]
+    fn test_unmatched_markers_are_kept() {
+        assert_eq!(render("2 * 3 * 4"), "2 * 3 * 4");
+        assert_eq!(render("snake_case_name"), "snake_case_name");
+        assert_eq!(render("\\*not italic\\*"), "*not italic*");
+        assert_eq!(render("[no link] (here)"), "[no link] (here)");
+    }
+
+    #[test]
+    fn test_links_are_restricted_and_marked_nofollow() {
+        assert_eq!(
+            render("[see **this**](https://example.com/?a=1&b=2)"),
+            "<a href=\"https://example.com/?a=1&amp;b=2\" rel=\"nofollow noopener\">see <strong>this</strong></a>"
+        );
+        assert_eq!(render("[click](javascript:alert(1))"), "[click](javascript:alert(1))");
+        assert_eq!(
+            render("[a [b](https://x.org)](https://y.org)"),
+            "[a <a href=\"https://x.org\" rel=\"nofollow noopener\">b</a>](https://y.org)"
+        );
+    }
+
+    #[test]
+    fn test_html_is_escaped() {
+        assert_eq!(render("<script>alert(1)</script>"), "&lt;script&gt;alert(1)&lt;/script&gt;");
+        assert_eq!(render("`<img src=x onerror=alert(1)>`"), "<code>&lt;img src=x onerror=alert(1)&gt;</code>");
+        assert_eq!(
+            render("[x](https://a.org\"onclick=\"alert(1))"),
+            "<a href=\"https://a.org&quot;onclick=&quot;alert(1\" rel=\"nofollow noopener\">x</a>)"
+        );
+        assert_eq!(render("[x](https://a.org onclick=alert(1))"), "[x](https://a.org onclick=alert(1))");
+    }
+}
@@ -2,50 +2,60 @@
 //! Fournit des fonctions pour démarrer et compléter les processus d'enregistrement et d'authentification.
 //! Inclut également des mécanismes pour la gestion sécurisée des passkeys et des tokens de récupération.
 
-use std::collections::HashMap;
 use anyhow::{Result, Context};
 use webauthn_rs::prelude::*;
 use once_cell::sync::Lazy;
-use url::Url;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use crate::{config, consts};
 
-// Initialisation globale de WebAuthn
+// Initialisation globale de WebAuthn, à partir de la Relying Party configurée
 static WEBAUTHN: Lazy<Webauthn> = Lazy::new(|| {
-    let rp_id = "localhost";
-    let rp_origin = Url::parse("http://localhost:8080").expect("Invalid RP origin URL");
+    let config = config::get();
+    let origins = config.origins();
 
-    WebauthnBuilder::new(rp_id, &rp_origin)
+    let mut builder = WebauthnBuilder::new(config.rp_id(), &origins[0])
         .expect("Failed to initialize WebAuthn")
-        .build()
-        .expect("Failed to build WebAuthn instance")
-});
+        .rp_name(config.webauthn.rp_name.as_deref().unwrap_or(config.rp_id()))
+        .timeout(Duration::from_secs(consts::WEBAUTHN_TIMEOUT_SECS));
+    for origin in &origins[1..] {
+        builder = builder.append_allowed_origin(origin);
+    }
 
-// Store sécurisé pour les passkeys
-pub static CREDENTIAL_STORE: Lazy<RwLock<HashMap<String, Passkey>>> = Lazy::new(Default::default);
+    builder.build().expect("Failed to build WebAuthn instance")
+});
 
-/// Démarrer l'enregistrement WebAuthn
-pub async fn begin_registration(
+/// Démarrer l'enregistrement WebAuthn, `user_handle` étant l'identifiant WebAuthn conservé pour l'utilisateur
+pub fn begin_registration(
+    user_handle: Uuid,
     user_email: &str,
     user_display_name: &str,
+    known_passkeys: &[Passkey],
 ) -> Result<(serde_json::Value, PasskeyRegistration)> {
-    let user_id = Uuid::new_v4();
-
-    // Exclude the known passkey for this user
-    let store = CREDENTIAL_STORE.read().await;
-    let exclude_credentials = store
-        .get(user_email)
-        .map(|pk| vec![pk.cred_id().clone()]);
+    // Exclude all the known passkeys for this user
+    let exclude_credentials = Some(
+        known_passkeys
+            .iter()
+            .map(|pk| pk.cred_id().clone())
+            .collect::<Vec<_>>(),
+    )
+    .filter(|creds| !creds.is_empty());
 
     // Start registration
     let (ccr, state) = WEBAUTHN
         .start_passkey_registration(
-            user_id,
+            user_handle,
             user_email,
             user_display_name,
-            exclude_credentials, // No credential options
+            exclude_credentials,
         )
         .context("Failed to start registration")?;
 
+    // Ask for a discoverable credential so that the passkey can be used without an email
+    let mut authenticator_selection = serde_json::to_value(&ccr.public_key.authenticator_selection)?;
+    if let Some(selection) = authenticator_selection.as_object_mut() {
+        selection.insert("residentKey".to_string(), "preferred".into());
+    }
+
     Ok((
         serde_json::json!({
             "rp": ccr.public_key.rp,
@@ -57,42 +67,29 @@ pub async fn begin_registration(
             "challenge": ccr.public_key.challenge,
             "pubKeyCredParams": ccr.public_key.pub_key_cred_params,
             "timeout": ccr.public_key.timeout,
-            "authenticatorSelection": ccr.public_key.authenticator_selection,
+            "authenticatorSelection": authenticator_selection,
             "attestation": ccr.public_key.attestation,
+            "excludeCredentials": ccr.public_key.exclude_credentials,
         }),
         state,
     ))
 }
 
-/// Compléter l'enregistrement WebAuthn
-pub async fn complete_registration(
-    user_email: &str,
+/// Compléter l'enregistrement WebAuthn, retourne la passkey créée
+pub fn complete_registration(
     response: &RegisterPublicKeyCredential,
     stored_state: &PasskeyRegistration,
-) -> Result<()> {
-    // Complete the registration
-    let passkey = WEBAUTHN
-        .finish_passkey_registration(response, &stored_state)
-        .context("Failed to complete registration")?;
-
-    // Store the credential
-    let mut store = CREDENTIAL_STORE.write().await;
-    store.insert(user_email.to_string(), passkey);
-
-    Ok(())
+) -> Result<Passkey> {
+    WEBAUTHN
+        .finish_passkey_registration(response, stored_state)
+        .context("Failed to complete registration")
 }
 
-/// Démarrer l'authentification WebAuthn
-pub async fn begin_authentication(user_email: &str) -> Result<(serde_json::Value, PasskeyAuthentication)> {
-    let store = CREDENTIAL_STORE.read().await;
-    let allowed_credentials = store
-        .get(user_email)
-        .map(|pk| vec![pk.clone()])
-        .unwrap_or_default();
-
+/// Démarrer l'authentification WebAuthn en autorisant toutes les passkeys de l'utilisateur
+pub fn begin_authentication(allowed_passkeys: &[Passkey]) -> Result<(serde_json::Value, PasskeyAuthentication)> {
     // Start authentication
     let (rcr, state) = WEBAUTHN
-        .start_passkey_authentication(&allowed_credentials)
+        .start_passkey_authentication(allowed_passkeys)
         .context("Failed to start authentication")?;
 
     Ok((
@@ -107,14 +104,48 @@ pub async fn begin_authentication(user_email: &str) -> Result<(serde_json::Value
 }
 
 /// Compléter l'authentification WebAuthn
-pub async fn complete_authentication(
+pub fn complete_authentication(
     response: &PublicKeyCredential,
     state: &PasskeyAuthentication,
-) -> Result<()> {
-    // Complete the authentication
+) -> Result<AuthenticationResult> {
     WEBAUTHN
         .finish_passkey_authentication(response, state)
-        .context("Failed to complete authentication")?;
+        .context("Failed to complete authentication")
+}
 
-    Ok(())
+/// Démarrer l'authentification WebAuthn sans identifier l'utilisateur, qui choisit une passkey découvrable
+pub fn begin_discoverable_authentication() -> Result<(serde_json::Value, DiscoverableAuthentication)> {
+    let (rcr, state) = WEBAUTHN
+        .start_discoverable_authentication()
+        .context("Failed to start authentication")?;
+
+    Ok((
+        serde_json::json!({
+            "challenge": rcr.public_key.challenge,
+            "timeout": rcr.public_key.timeout,
+            "rpId": rcr.public_key.rp_id,
+            "userVerification": rcr.public_key.user_verification,
+        }),
+        state,
+    ))
+}
+
+/// Retourne l'identifiant WebAuthn de l'utilisateur auquel appartient la passkey utilisée
+pub fn identify_discoverable_authentication(response: &PublicKeyCredential) -> Result<Uuid> {
+    let (user_handle, _) = WEBAUTHN
+        .identify_discoverable_authentication(response)
+        .context("Failed to identify the user")?;
+    Ok(user_handle)
+}
+
+/// Compléter l'authentification WebAuthn sans identifiant, parmi les passkeys de l'utilisateur identifié
+pub fn complete_discoverable_authentication(
+    response: &PublicKeyCredential,
+    state: DiscoverableAuthentication,
+    passkeys: &[Passkey],
+) -> Result<AuthenticationResult> {
+    let keys = passkeys.iter().map(DiscoverableKey::from).collect::<Vec<_>>();
+    WEBAUTHN
+        .finish_discoverable_authentication(response, state, &keys)
+        .context("Failed to complete authentication")
 }
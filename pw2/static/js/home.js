@@ -0,0 +1,203 @@
+const csrfToken = document.querySelector("meta[name='csrf-token']").content;
+
+const fileInput = document.getElementById("file");
+const imagePreview = document.getElementById("image-preview");
+const previewImg = document.getElementById("preview-img");
+const removeImageButton = document.getElementById("remove-image");
+
+fileInput.addEventListener("change", (event) => {
+    const file = event.target.files[0];
+    if (file) {
+        const reader = new FileReader();
+        reader.onload = (e) => {
+            previewImg.src = e.target.result;
+            imagePreview.classList.remove("d-none");
+        };
+        reader.readAsDataURL(file);
+    }
+});
+
+removeImageButton.addEventListener("click", () => {
+    fileInput.value = ""; // Clear the file input
+    imagePreview.classList.add("d-none");
+});
+
+document.getElementById("publish_post").addEventListener("click", submitPost);
+
+const postsList = document.getElementById("posts_list");
+
+postsList.addEventListener("click", (event) => {
+    if (event.target.classList.contains("post-image")) {
+        const fullImage = document.querySelector("#imageModal img");
+        fullImage.srcset = event.target.getAttribute("data-srcset");
+        fullImage.src = event.target.getAttribute("data-src");
+        return;
+    }
+
+    const button = event.target.closest("[data-action]");
+    if (!button) {
+        return;
+    }
+    const postId = button.closest("[data-post-id]").dataset.postId;
+    switch (button.dataset.action) {
+        case "edit-post":
+            editPost(postId);
+            break;
+        case "delete-post":
+            deletePost(postId);
+            break;
+        case "react":
+            likePost(postId, button.dataset.reaction);
+            break;
+        case "reply":
+            replyTo(button);
+            break;
+        case "delete-comment":
+            deleteComment(button);
+            break;
+    }
+});
+
+postsList.addEventListener("submit", (event) => {
+    if (event.target.classList.contains("comment-form")) {
+        event.preventDefault();
+        addComment(event.target);
+    }
+});
+
+async function submitPost() {
+    const formData = new FormData();
+    formData.append("text", document.getElementById("text").value);
+    const fileInput = document.getElementById("file");
+    if (fileInput.files.length > 0) {
+        formData.append("file", fileInput.files[0]);
+    }
+
+    try {
+        const response = await fetch("/post/create", {
+            method: "POST",
+            headers: { "X-CSRF-Token": csrfToken },
+            body: formData,
+        });
+
+        if (response.ok) {
+            location.reload();
+        } else {
+            const errorText = await response.text();
+            alert("Failed to create post: " + errorText);
+        }
+    } catch (error) {
+        alert("An error occurred: " + error.message);
+    }
+}
+
+async function editPost(postId) {
+    const current = document.getElementById(`content-${postId}`).dataset.source;
+    const text = prompt("Edit your post:", current);
+    if (text === null || text === current) {
+        return;
+    }
+
+    const response = await fetch("/post/edit", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken },
+        body: JSON.stringify({ post_id: postId, text }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to edit post: " + await response.text());
+    }
+}
+
+async function deletePost(postId) {
+    if (!confirm("Delete this post?")) {
+        return;
+    }
+
+    const response = await fetch("/post/delete", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken },
+        body: JSON.stringify({ post_id: postId }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to delete post: " + await response.text());
+    }
+}
+
+async function addComment(form) {
+    const postId = form.closest("[data-post-id]").dataset.postId;
+    const response = await fetch("/post/comment", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken },
+        body: JSON.stringify({
+            post_id: postId,
+            parent_id: form.elements.parent_id.value || null,
+            text: form.elements.text.value,
+        }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to add comment: " + await response.text());
+    }
+}
+
+function replyTo(button) {
+    const comment = button.closest("[data-comment-id]");
+    const form = button.closest("[data-post-id]").querySelector("form");
+    form.elements.parent_id.value = comment.dataset.commentId;
+    form.elements.text.placeholder = "Reply to " + comment.querySelector(".text-muted").textContent;
+    form.elements.text.focus();
+}
+
+async function deleteComment(button) {
+    if (!confirm("Delete this comment?")) {
+        return;
+    }
+
+    const response = await fetch("/post/comment/delete", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken },
+        body: JSON.stringify({
+            post_id: button.closest("[data-post-id]").dataset.postId,
+            comment_id: button.closest("[data-comment-id]").dataset.commentId,
+        }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to delete comment: " + await response.text());
+    }
+}
+
+async function likePost(postId, action) {
+    try {
+        const response = await fetch("/post/like", {
+            method: "POST",
+            headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken },
+            body: JSON.stringify({ post_id: postId, action }),
+        });
+
+        if (response.ok) {
+            const summary = await response.json();
+            document.getElementById(`likes-${postId}`).textContent = summary.likes;
+            document.getElementById(`dislikes-${postId}`).textContent = summary.dislikes;
+            document.getElementById(`like-${postId}`).className =
+                "btn " + (summary.own_reaction === "like" ? "btn-success" : "btn-outline-success");
+            document.getElementById(`dislike-${postId}`).className =
+                "btn " + (summary.own_reaction === "dislike" ? "btn-danger" : "btn-outline-danger");
+        } else {
+            const errorText = await response.text();
+            alert("Failed to update like/dislike: " + errorText);
+        }
+    } catch (error) {
+        alert("An error occurred: " + error.message);
+    }
+}
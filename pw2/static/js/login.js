@@ -0,0 +1,144 @@
+const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+
+// Pending passkey autofill request, cancelled before any other authentication
+let autofill = null;
+
+document.getElementById('login_form').addEventListener('submit', (event) => {
+    event.preventDefault();
+    startLogin();
+});
+
+document.getElementById('passkey_login').addEventListener('click', () => {
+    startDiscoverableLogin('optional');
+});
+
+// Offer the passkeys of this site in the autofill prompt of the email field
+if (window.PublicKeyCredential && PublicKeyCredential.isConditionalMediationAvailable) {
+    PublicKeyCredential.isConditionalMediationAvailable().then((available) => {
+        if (available) {
+            startDiscoverableLogin('conditional');
+        }
+    });
+}
+
+function decode(value) {
+    return Uint8Array.from(atob(value.replace(/-/g, '+').replace(/_/g, '/')), c => c.charCodeAt(0));
+}
+
+function serializeAssertion(assertion) {
+    return {
+        id: assertion.id,
+        rawId: Array.from(new Uint8Array(assertion.rawId)),
+        response: {
+            clientDataJSON: Array.from(new Uint8Array(assertion.response.clientDataJSON)),
+            authenticatorData: Array.from(new Uint8Array(assertion.response.authenticatorData)),
+            signature: Array.from(new Uint8Array(assertion.response.signature)),
+            userHandle: assertion.response.userHandle ? Array.from(new Uint8Array(assertion.response.userHandle)) : null,
+        },
+        type: assertion.type,
+    };
+}
+
+function cancelAutofill() {
+    if (autofill) {
+        autofill.abort();
+        autofill = null;
+    }
+}
+
+async function startLogin() {
+    cancelAutofill();
+    const email = document.getElementById("email").value;
+
+    try {
+        const response = await fetch('/login', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({ email })
+        });
+
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+
+        const data = await response.json();
+        const publicKey = data.publicKey;
+
+        if (publicKey.allowCredentials) {
+            publicKey.allowCredentials = publicKey.allowCredentials.map((cred) => ({
+                ...cred,
+                id: decode(cred.id)
+            }));
+        }
+
+        publicKey.challenge = decode(publicKey.challenge);
+
+        const assertion = await navigator.credentials.get({ publicKey });
+
+        const loginResponse = await fetch('/login/complete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({
+                email,
+                response: serializeAssertion(assertion),
+                state_id: data.state_id,
+            })
+        });
+
+        if (loginResponse.ok) {
+            window.location.href = "/home";
+        } else {
+            alert('Login failed.');
+        }
+    } catch (error) {
+        alert("Failed to authenticate. Ensure you're using localhost or HTTPS.");
+    }
+}
+
+// Sign in without an email, the server finds the user from the chosen passkey.
+// `mediation` is 'conditional' for the autofill prompt and 'optional' for the button.
+async function startDiscoverableLogin(mediation) {
+    cancelAutofill();
+    const controller = new AbortController();
+    if (mediation === 'conditional') {
+        autofill = controller;
+    }
+
+    try {
+        const response = await fetch('/login/discoverable', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({})
+        });
+
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+
+        const data = await response.json();
+        const publicKey = data.publicKey;
+        publicKey.challenge = decode(publicKey.challenge);
+
+        const assertion = await navigator.credentials.get({ publicKey, mediation, signal: controller.signal });
+
+        const loginResponse = await fetch('/login/discoverable/complete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({
+                response: serializeAssertion(assertion),
+                state_id: data.state_id,
+            })
+        });
+
+        if (loginResponse.ok) {
+            window.location.href = "/home";
+        } else {
+            alert('Login failed.');
+        }
+    } catch (error) {
+        // Cancelling the autofill prompt to use another method is not an error
+        if (error.name !== 'AbortError') {
+            alert("Failed to authenticate. Ensure you're using localhost or HTTPS.");
+        }
+    }
+}
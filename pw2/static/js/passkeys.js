@@ -0,0 +1,111 @@
+const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+
+const base64UrlToBytes = (value) => Uint8Array.from(
+        atob(value.replace(/-/g, '+').replace(/_/g, '/')),
+        c => c.charCodeAt(0)
+);
+
+document.getElementById('add_passkey_form').addEventListener('submit', (event) => {
+    event.preventDefault();
+    addPasskey();
+});
+
+document.getElementById('passkeys_list').addEventListener('click', (event) => {
+    const button = event.target.closest('[data-action]');
+    if (!button) {
+        return;
+    }
+    const credentialId = button.closest('[data-credential-id]').dataset.credentialId;
+    if (button.dataset.action === 'rename') {
+        renamePasskey(credentialId);
+    } else if (button.dataset.action === 'revoke') {
+        revokePasskey(credentialId);
+    }
+});
+
+async function addPasskey() {
+    const name = document.getElementById('passkey_name').value;
+
+    try {
+        const response = await fetch('/passkeys/register', { method: 'POST', headers: { 'X-CSRF-Token': csrfToken } });
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+
+        const data = await response.json();
+        const publicKeyOptions = data.publicKey;
+
+        publicKeyOptions.user.id = Uint8Array.from(publicKeyOptions.user.id);
+        publicKeyOptions.challenge = base64UrlToBytes(publicKeyOptions.challenge);
+        if (publicKeyOptions.excludeCredentials) {
+            publicKeyOptions.excludeCredentials = publicKeyOptions.excludeCredentials.map((cred) => ({
+                ...cred,
+                id: base64UrlToBytes(cred.id),
+            }));
+        }
+
+        const credential = await navigator.credentials.create({ publicKey: publicKeyOptions });
+
+        const completeResponse = await fetch('/passkeys/register/complete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({
+                name,
+                state_id: data.state_id,
+                response: {
+                    id: credential.id,
+                    rawId: Array.from(new Uint8Array(credential.rawId)),
+                    response: {
+                        clientDataJSON: Array.from(new Uint8Array(credential.response.clientDataJSON)),
+                        attestationObject: Array.from(new Uint8Array(credential.response.attestationObject)),
+                    },
+                    type: credential.type,
+                },
+            })
+        });
+
+        if (!completeResponse.ok) {
+            throw new Error(await completeResponse.text());
+        }
+        location.reload();
+    } catch (error) {
+        alert("Failed to add passkey: " + error.message);
+    }
+}
+
+async function renamePasskey(credentialId) {
+    const name = prompt("New name for this passkey:");
+    if (!name) {
+        return;
+    }
+
+    const response = await fetch('/passkeys/rename', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+        body: JSON.stringify({ credential_id: credentialId, name }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to rename passkey: " + await response.text());
+    }
+}
+
+async function revokePasskey(credentialId) {
+    if (!confirm("Revoke this passkey? It will no longer be able to sign in.")) {
+        return;
+    }
+
+    const response = await fetch('/passkeys/revoke', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+        body: JSON.stringify({ credential_id: credentialId }),
+    });
+
+    if (response.ok) {
+        location.reload();
+    } else {
+        alert("Failed to revoke passkey: " + await response.text());
+    }
+}
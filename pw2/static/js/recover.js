@@ -0,0 +1,28 @@
+const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+
+document.getElementById('recover_form').addEventListener('submit', (event) => {
+    event.preventDefault();
+    startRecovery();
+});
+
+async function startRecovery() {
+    const email = document.getElementById("email").value;
+
+    try {
+        const response = await fetch('/recover', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({ email })
+        });
+
+        if (response.ok) {
+            document.getElementById("recovery_status").textContent = "Recovery email sent! Check your inbox.";
+            document.getElementById("recovery_status").classList.add("alert", "alert-success");
+        } else {
+            throw new Error(await response.text());
+        }
+    } catch (error) {
+        document.getElementById("recovery_status").textContent = "Recovery failed: " + error.message;
+        document.getElementById("recovery_status").classList.add("alert", "alert-danger");
+    }
+}
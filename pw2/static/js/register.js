@@ -0,0 +1,75 @@
+const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+
+const resetMode = document.getElementById('register_form').dataset.resetMode === 'true';
+
+document.getElementById('register_form').addEventListener('submit', (event) => {
+    event.preventDefault();
+    startRegistration();
+});
+
+async function startRegistration() {
+    const email = document.getElementById('email').value;
+    const firstName = document.getElementById('first_name').value;
+    const lastName = document.getElementById('last_name').value;
+
+    try {
+        const response = await fetch('/register', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({ email, reset_mode: resetMode })
+        });
+
+        if (!response.ok) {
+            throw new Error(await response.text());
+        }
+
+        const data = await response.json();
+        const publicKeyOptions = data.publicKey;
+
+        publicKeyOptions.user.id = Uint8Array.from(publicKeyOptions.user.id);
+        publicKeyOptions.challenge = Uint8Array.from(
+                atob(publicKeyOptions.challenge.replace(/-/g, '+').replace(/_/g, '/'))
+                        .split('').map(c => c.charCodeAt(0))
+        );
+        if (publicKeyOptions.excludeCredentials) {
+            publicKeyOptions.excludeCredentials = publicKeyOptions.excludeCredentials.map((cred) => ({
+                ...cred,
+                id: Uint8Array.from(atob(cred.id.replace(/-/g, '+').replace(/_/g, '/')), c => c.charCodeAt(0))
+            }));
+        }
+
+        const credential = await navigator.credentials.create({ publicKey: publicKeyOptions });
+
+        const credentialJson = {
+            id: credential.id,
+            rawId: Array.from(new Uint8Array(credential.rawId)),
+            response: {
+                clientDataJSON: Array.from(new Uint8Array(credential.response.clientDataJSON)),
+                attestationObject: Array.from(new Uint8Array(credential.response.attestationObject)),
+            },
+            type: credential.type,
+        };
+
+        const completeResponse = await fetch('/register/complete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+            body: JSON.stringify({
+                email,
+                first_name: firstName,
+                last_name: lastName,
+                response: credentialJson,
+                state_id: data.state_id,
+                reset_mode: resetMode
+            })
+        });
+
+        if (completeResponse.ok) {
+            document.getElementById('registration_status').textContent = "Registration successful! You can now log in.";
+            document.getElementById('registration_status').classList.add("alert", "alert-success");
+        } else {
+            throw new Error(await completeResponse.text());
+        }
+    } catch (error) {
+        alert("Registration failed: " + error.message);
+    }
+}